@@ -0,0 +1,74 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::seq::IteratorRandom;
+use rand::{rngs::StdRng, SeedableRng};
+use reversi::board::{Board, Player, Pos};
+use reversi::movegen::{kogge_stone, lut, shift, simd};
+
+type Backend = (&'static str, fn(u64, u64) -> u64, fn(u64, u64, Pos) -> u64);
+const BACKENDS: [Backend; 4] = [
+    (shift::NAME, shift::moves, shift::flips),
+    (kogge_stone::NAME, kogge_stone::moves, kogge_stone::flips),
+    (lut::NAME, lut::moves, lut::flips),
+    (simd::NAME, simd::moves, simd::flips),
+];
+
+/// (my disks, opponent disks) of every position of a few random games, checked against
+/// `valid_moves_slow` for every backend so the timings compare implementations that agree.
+fn positions() -> Vec<(u64, u64)> {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut res = Vec::new();
+    for _ in 0..100 {
+        let mut board = Board::initial();
+        let mut p = Player::Black;
+        while !board.is_final() {
+            let my_disks = board.get_disks(p).bits();
+            let opp_disks = board.get_disks(p.opponent()).bits();
+            let expected = board.valid_moves_slow(p).fold(0, |acc, a| acc | a.at.mask());
+            for (name, moves, _) in BACKENDS {
+                assert_eq!(moves(my_disks, opp_disks), expected, "{} disagrees with valid_moves_slow", name);
+            }
+            res.push((my_disks, opp_disks));
+            if let Some(action) = board.valid_moves(p).choose(&mut rng) {
+                board = action.board;
+            }
+            p.flip();
+        }
+    }
+    res
+}
+
+fn bench_moves(c: &mut Criterion) {
+    let positions = positions();
+    let mut group = c.benchmark_group("moves");
+    for (name, moves, _) in BACKENDS {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &positions, |b, positions| {
+            b.iter(|| {
+                positions
+                    .iter()
+                    .fold(0u64, |acc, &(my, opp)| acc ^ moves(black_box(my), black_box(opp)))
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_flips(c: &mut Criterion) {
+    let positions: Vec<_> = positions()
+        .into_iter()
+        .flat_map(|(my, opp)| Pos::iter_mask(shift::moves(my, opp)).map(move |pos| (my, opp, pos)))
+        .collect();
+    let mut group = c.benchmark_group("flips");
+    for (name, _, flips) in BACKENDS {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &positions, |b, positions| {
+            b.iter(|| {
+                positions.iter().fold(0u64, |acc, &(my, opp, pos)| {
+                    acc ^ flips(black_box(my), black_box(opp), black_box(pos))
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_moves, bench_flips);
+criterion_main!(benches);
@@ -1,12 +1,67 @@
 
-use crate::board::{Action, Board, Player, Pos};
+use crate::board::{Action, Board, Player, SquareSet};
+use crate::command::Wl;
 use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
 use rand::seq::IteratorRandom;
 
+/// How a game ended, from the agent's point of view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameEnd {
+    pub result: Wl,
+    pub my_score: u32,
+    pub opponent_score: u32,
+    /// Why the game ended, as given by the server, e.g. `DOUBLE_PASS`.
+    pub reason: String,
+}
+
+impl GameEnd {
+    /// End of a game played out to `board`, for player `p`.
+    pub fn from_board(board: &Board, p: Player, reason: impl Into<String>) -> Self {
+        let my_score = board.count(p);
+        let opponent_score = board.count(p.opponent());
+        Self {
+            result: match my_score.cmp(&opponent_score) {
+                std::cmp::Ordering::Greater => Wl::Win,
+                std::cmp::Ordering::Less => Wl::Lose,
+                std::cmp::Ordering::Equal => Wl::Tie,
+            },
+            my_score,
+            opponent_score,
+            reason: reason.into(),
+        }
+    }
+}
+
 pub trait Agent {
     fn initialize(&mut self) {}
     fn opponent_move_callback(&mut self, _action: Option<Action>) {}
     fn select_move(&mut self, board: &Board, p: Player) -> Option<Action>;
+    /// Called once a game is over, before `initialize` for the next one.
+    fn game_end(&mut self, _end: &GameEnd) {}
+    /// Hands over a flag that is raised, for good, once the agent's searches are no longer
+    /// wanted, e.g. when a search running on a thread of its own is given up. Agents that
+    /// search for long should poll it and return as soon as it is raised; what they return
+    /// then is ignored.
+    fn set_stop(&mut self, _stop: Arc<AtomicBool>) {}
+}
+impl<A: Agent + ?Sized> Agent for Box<A> {
+    fn initialize(&mut self) {
+        (**self).initialize()
+    }
+    fn game_end(&mut self, end: &GameEnd) {
+        (**self).game_end(end)
+    }
+    fn opponent_move_callback(&mut self, action: Option<Action>) {
+        (**self).opponent_move_callback(action)
+    }
+    fn select_move(&mut self, board: &Board, p: Player) -> Option<Action> {
+        (**self).select_move(board, p)
+    }
+    fn set_stop(&mut self, stop: Arc<AtomicBool>) {
+        (**self).set_stop(stop)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -17,33 +72,46 @@ impl Agent for RandomAgent {
         board.valid_moves(p).choose(&mut rand::thread_rng())
     }
 }
+/// Plays at random from its own seed, so that games against it are the same on every run.
+#[cfg(test)]
+pub(crate) struct SeededRandomAgent(rand::rngs::StdRng);
+#[cfg(test)]
+impl SeededRandomAgent {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(rand::SeedableRng::seed_from_u64(seed))
+    }
+}
+#[cfg(test)]
+impl Agent for SeededRandomAgent {
+    fn opponent_move_callback(&mut self, _action: Option<Action>) {}
+    fn select_move(&mut self, board: &Board, p: Player) -> Option<Action> {
+        board.valid_moves(p).choose(&mut self.0)
+    }
+}
 pub struct OneStepLookaheadAgent;
 impl Agent for OneStepLookaheadAgent {
     fn opponent_move_callback(&mut self, _action: Option<Action>) {}
     fn select_move(&mut self, board: &Board, p: Player) -> Option<Action> {
         // if corner is available, take it; otherwise, take move randomly
         let moves: Vec<_> = board.valid_moves(p).collect();
-        if let Some(corner) = moves.iter().find(|m| match m.at {
-            Pos::A1 | Pos::A8 | Pos::H1 | Pos::H8 => true,
-            _ => false,
-        }) {
+        if let Some(corner) = moves.iter().find(|m| SquareSet::CORNERS.contains(m.at)) {
             return Some(*corner);
         }
 
         if let Some(action) = moves
             .iter()
-            .filter(|m| m.board.valid_moves_fast(p.opponent()) & 0x8100000000000081 == 0)
+            .filter(|m| m.board.valid_moves_fast(p.opponent()).is_disjoint(SquareSet::CORNERS))
             .choose(&mut rand::thread_rng())
         {
             return Some(*action);
         }
-        return moves.into_iter().choose(&mut rand::thread_rng());
+        moves.into_iter().choose(&mut rand::thread_rng())
     }
 }
 
 pub fn bfs(board: &Board, p: Player, depth: usize) -> HashSet<(Player, Board)> {
-    let mut vis = HashSet::from([(p, board.clone())]);
-    let mut q = VecDeque::from([(depth, p, board.clone())]);
+    let mut vis = HashSet::from([(p, *board)]);
+    let mut q = VecDeque::from([(depth, p, *board)]);
     while let Some((d, p, b)) = q.pop_front() {
         let actions: Vec<_> = b.valid_moves(p).collect();
         let o = p.opponent();
@@ -0,0 +1,394 @@
+//! Evaluation of every legal move of a position, for reviewing games.
+use crate::agent::{Agent, RandomAgent};
+use crate::board::{Board, Player, Pos};
+use crate::dfs_agent::DfsAgent;
+use std::fmt::{self, Display};
+use std::time::{Duration, Instant};
+
+/// How much searching an [`Evaluator`] may do for one position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Budget {
+    Nodes(u64),
+    Time(Duration),
+}
+
+/// Value of a position for the player to move.
+///
+/// `Exact` is the final disc margin under perfect play. `Line` is the final disc margin at the
+/// end of the principal variation of a search that cut other lines short, so a likely result
+/// rather than a proven one. `Estimate` is in units of the evaluator that made it, always below
+/// 1000 in magnitude, so that wins on the disc scale rank above and losses below every estimate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Score {
+    Exact(i32),
+    Line(i32),
+    Estimate(f64),
+}
+
+impl Score {
+    /// Key to rank scores of the same evaluator by.
+    pub fn key(&self) -> f64 {
+        match *self {
+            Score::Exact(margin) | Score::Line(margin) => 1000.0 * margin as f64,
+            Score::Estimate(value) => value,
+        }
+    }
+
+    /// The disc margin of an exact or line score.
+    pub fn margin(&self) -> Option<i32> {
+        match *self {
+            Score::Exact(margin) | Score::Line(margin) => Some(margin),
+            Score::Estimate(_) => None,
+        }
+    }
+}
+
+impl std::ops::Neg for Score {
+    type Output = Score;
+    fn neg(self) -> Score {
+        match self {
+            Score::Exact(margin) => Score::Exact(-margin),
+            Score::Line(margin) => Score::Line(-margin),
+            Score::Estimate(value) => Score::Estimate(-value),
+        }
+    }
+}
+
+impl Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Score::Exact(margin) => write!(f, "{:+} exact", margin),
+            Score::Line(margin) => write!(f, "{:+} in pv", margin),
+            Score::Estimate(value) => write!(f, "{:+.1}", value),
+        }
+    }
+}
+
+/// What an [`Evaluator`] found out about a position.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    pub score: Score,
+    /// Best play from the position as far as known, `None` for a pass.
+    pub pv: Vec<Option<Pos>>,
+    /// Depth of the deepest completed search, 0 if it does not search by depth.
+    pub depth: usize,
+    pub nodes: u64,
+}
+
+pub trait Evaluator {
+    fn search(&mut self, board: &Board, p: Player, budget: Budget) -> Evaluation;
+}
+
+/// Iterative deepening alpha-beta on the evaluation of [`DfsAgent`], which solves the
+/// position exactly once the budget allows searching to the end of the game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlphaBeta {
+    pub max_depth: usize,
+}
+
+impl Default for AlphaBeta {
+    fn default() -> Self {
+        // deep enough for any game, passes included
+        Self { max_depth: 80 }
+    }
+}
+
+struct Search {
+    nodes: u64,
+    budget: Budget,
+    start: Instant,
+    stopped: bool,
+    /// Whether a leaf was scored by the heuristic rather than played out.
+    heuristic: bool,
+}
+
+impl Search {
+    fn out_of_budget(&self) -> bool {
+        match self.budget {
+            Budget::Nodes(n) => self.nodes >= n,
+            Budget::Time(t) => self.nodes.is_multiple_of(1024) && self.start.elapsed() >= t,
+        }
+    }
+
+    /// Negamax score of `board` for `p` within `(alpha, beta)`, with its line in `pv`.
+    fn negamax(&mut self, board: &Board, p: Player, depth: usize, mut alpha: i32, beta: i32, pv: &mut Vec<Option<Pos>>) -> i32 {
+        pv.clear();
+        self.nodes += 1;
+        if self.out_of_budget() {
+            self.stopped = true;
+            return 0;
+        }
+        if board.is_final() {
+            return DfsAgent::evaluate(board, p);
+        }
+        if depth == 0 {
+            self.heuristic = true;
+            return DfsAgent::evaluate(board, p);
+        }
+        let o = p.opponent();
+        let mut moves: Vec<_> = board.valid_moves(p).collect();
+        let mut line = Vec::new();
+        if moves.is_empty() {
+            let score = -self.negamax(board, o, depth - 1, -beta, -alpha, &mut line);
+            pv.push(None);
+            pv.append(&mut line);
+            return score;
+        }
+        if depth > 2 {
+            // fastest first: the fewer replies, the sooner the cutoffs
+            moves.sort_by_key(|a| a.board.valid_moves_fast(o).len());
+        }
+        let mut best = i32::MIN + 1;
+        for action in moves {
+            let score = -self.negamax(&action.board, o, depth - 1, -beta, -alpha, &mut line);
+            if self.stopped {
+                return 0;
+            }
+            if score > best {
+                best = score;
+                pv.clear();
+                pv.push(Some(action.at));
+                pv.append(&mut line);
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+        best
+    }
+}
+
+impl Evaluator for AlphaBeta {
+    fn search(&mut self, board: &Board, p: Player, budget: Budget) -> Evaluation {
+        let mut search = Search {
+            nodes: 0,
+            budget,
+            start: Instant::now(),
+            stopped: false,
+            heuristic: false,
+        };
+        let mut best = Evaluation {
+            score: Score::Estimate(DfsAgent::evaluate(board, p) as f64),
+            pv: Vec::new(),
+            depth: 0,
+            nodes: 0,
+        };
+        for depth in 1..=self.max_depth {
+            search.heuristic = false;
+            let mut pv = Vec::new();
+            let score = search.negamax(board, p, depth, i32::MIN + 1, i32::MAX, &mut pv);
+            if search.stopped {
+                break;
+            }
+            // the heuristic stays below 1000, finished games count 1000 per disc
+            best.score = match (search.heuristic, final_margin(board, p, &pv)) {
+                (false, _) => Score::Exact(score / 1000),
+                (true, Some(margin)) if score == 1000 * margin => Score::Line(margin),
+                (true, _) => Score::Estimate(score as f64),
+            };
+            best.pv = pv;
+            best.depth = depth;
+            if !search.heuristic {
+                break;
+            }
+        }
+        best.nodes = search.nodes;
+        best
+    }
+}
+
+/// Disc margin for `p` at the end of `pv` played from `board`, if the game is over there.
+fn final_margin(board: &Board, p: Player, pv: &[Option<Pos>]) -> Option<i32> {
+    let (mut board, mut q) = (*board, p);
+    for at in pv {
+        if let Some(at) = *at {
+            board.make_move(q, at)?;
+        }
+        q.flip();
+    }
+    board
+        .is_final()
+        .then(|| board.count(p) as i32 - board.count(p.opponent()) as i32)
+}
+
+/// Mean final disc margin of games played out at random.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Playouts;
+
+impl Evaluator for Playouts {
+    fn search(&mut self, board: &Board, p: Player, budget: Budget) -> Evaluation {
+        let start = Instant::now();
+        let (mut nodes, mut n_games, mut total) = (0, 0, 0i64);
+        loop {
+            let (mut board, mut q) = (*board, p);
+            while !board.is_final() {
+                if let Some(action) = RandomAgent.select_move(&board, q) {
+                    board = action.board;
+                }
+                q.flip();
+                nodes += 1;
+            }
+            total += board.count(p) as i64 - board.count(p.opponent()) as i64;
+            n_games += 1;
+            let done = match budget {
+                Budget::Nodes(n) => nodes >= n,
+                Budget::Time(t) => start.elapsed() >= t,
+            };
+            if done || nodes == 0 {
+                break;
+            }
+        }
+        let score = match nodes {
+            // the game is over already
+            0 => Score::Exact(total as i32),
+            _ => Score::Estimate(total as f64 / n_games as f64),
+        };
+        Evaluation {
+            score,
+            pv: Vec::new(),
+            depth: 0,
+            nodes,
+        }
+    }
+}
+
+/// Evaluation of one of the moves of a position, for the player making it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveAnalysis {
+    /// The move, `None` for a pass.
+    pub at: Option<Pos>,
+    /// Evaluation of the move, its principal variation starting with the move itself.
+    pub evaluation: Evaluation,
+}
+
+/// Evaluates every legal move of `board` for `p` with `evaluator`, each with `budget`, best
+/// first. Without a legal move, the pass is the only one.
+pub fn analyze(board: &Board, p: Player, evaluator: &mut dyn Evaluator, budget: Budget) -> Vec<MoveAnalysis> {
+    let moves: Vec<_> = board.valid_moves(p).map(|a| (Some(a.at), a.board)).collect();
+    let moves = if moves.is_empty() { vec![(None, *board)] } else { moves };
+    let mut analyses: Vec<_> = moves
+        .into_iter()
+        .map(|(at, next)| {
+            let reply = evaluator.search(&next, p.opponent(), budget);
+            let mut pv = vec![at];
+            pv.extend(reply.pv);
+            MoveAnalysis {
+                at,
+                evaluation: Evaluation {
+                    score: -reply.score,
+                    pv,
+                    depth: reply.depth,
+                    nodes: reply.nodes,
+                },
+            }
+        })
+        .collect();
+    analyses.sort_by(|a, b| b.evaluation.score.key().total_cmp(&a.evaluation.score.key()));
+    analyses
+}
+
+/// Writes a move as in the protocol, `PASS` for a pass.
+pub fn move_name(at: Option<Pos>) -> String {
+    at.map_or("PASS".to_string(), |pos| pos.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Position;
+
+    /// Exact margin by plain minimax, for small endgames.
+    fn minimax(board: &Board, p: Player) -> i32 {
+        if board.is_final() {
+            return board.count(p) as i32 - board.count(p.opponent()) as i32;
+        }
+        match board.valid_moves(p).map(|a| -minimax(&a.board, p.opponent())).max() {
+            Some(score) => score,
+            None => -minimax(board, p.opponent()),
+        }
+    }
+
+    /// A position `empties` moves from the end, reached by random play.
+    fn endgame(empties: u32, seed: u64) -> (Board, Player) {
+        use rand::{seq::IteratorRandom, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        loop {
+            let (mut board, mut p) = (Board::initial(), Player::Black);
+            while !board.is_final() && 64 - board.count(Player::Black) - board.count(Player::White) > empties {
+                if let Some(action) = board.valid_moves(p).choose(&mut rng) {
+                    board = action.board;
+                }
+                p.flip();
+            }
+            if !board.is_final() {
+                return (board, p);
+            }
+        }
+    }
+
+    #[test]
+    fn test_exact_endgame() {
+        for seed in 0..5 {
+            let (board, p) = endgame(8, seed);
+            let evaluation = AlphaBeta::default().search(&board, p, Budget::Nodes(u64::MAX));
+            assert_eq!(evaluation.score, Score::Exact(minimax(&board, p)));
+            // playing out the principal variation gives the score
+            let (mut end, mut q) = (board, p);
+            for at in &evaluation.pv {
+                if let Some(at) = at {
+                    let action = end.valid_moves(q).find(|a| a.at == *at).expect("legal move");
+                    end = action.board;
+                }
+                q.flip();
+            }
+            assert!(end.is_final());
+            assert_eq!(end.count(p) as i32 - end.count(p.opponent()) as i32, minimax(&board, p));
+        }
+    }
+
+    #[test]
+    fn test_analyze() {
+        let (board, p) = endgame(6, 7);
+        let analyses = analyze(&board, p, &mut AlphaBeta::default(), Budget::Nodes(u64::MAX));
+        assert_eq!(analyses.len(), board.valid_moves(p).count().max(1));
+        assert_eq!(analyses[0].evaluation.score, Score::Exact(minimax(&board, p)));
+        assert!(analyses.windows(2).all(|w| w[0].evaluation.score.key() >= w[1].evaluation.score.key()));
+        assert!(analyses.iter().all(|a| a.evaluation.pv[0] == a.at));
+    }
+
+    #[test]
+    fn test_budget() {
+        let position: Position = "---------------------------OX------XO--------------------------- X".parse().unwrap();
+        let evaluation = AlphaBeta::default().search(&position.board, position.to_move, Budget::Nodes(5000));
+        assert!(matches!(evaluation.score, Score::Estimate(_)));
+        assert!(evaluation.depth >= 3);
+        assert_eq!(evaluation.pv.len(), evaluation.depth);
+        let start = Instant::now();
+        let evaluation = Playouts.search(&position.board, position.to_move, Budget::Time(Duration::from_millis(50)));
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert!(matches!(evaluation.score, Score::Estimate(m) if m.abs() <= 64.0));
+        let analyses = analyze(&position.board, position.to_move, &mut Playouts, Budget::Nodes(1000));
+        assert_eq!(analyses.len(), 4);
+    }
+
+    #[test]
+    fn test_line() {
+        use crate::board::SquareSet;
+        // C1 takes both white discs and ends the game, C3 leaves one to a heuristic leaf
+        let board = Board::from_disks(SquareSet::from(Pos::A1) | Pos::A3, SquareSet::from(Pos::B1) | Pos::B2);
+        let evaluation = AlphaBeta { max_depth: 1 }.search(&board, Player::Black, Budget::Nodes(u64::MAX));
+        assert_eq!(evaluation.score, Score::Line(5));
+        assert_eq!(evaluation.pv, [Some(Pos::C1)]);
+        assert_eq!(evaluation.score.to_string(), "+5 in pv");
+        // estimates of depth-limited searches of random positions stay off the disc scale
+        for seed in 0..5 {
+            let (board, p) = endgame(20, seed);
+            for analysis in analyze(&board, p, &mut AlphaBeta { max_depth: 3 }, Budget::Nodes(u64::MAX)) {
+                if let Score::Estimate(value) = analysis.evaluation.score {
+                    assert!(value.abs() < 1000.0, "{}", value);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,285 @@
+//! Replays recorded games and marks the moves that lost the most, for reviewing agents.
+use crate::analysis::{self, AlphaBeta, Budget, Evaluator, Score};
+use crate::board::{Board, Player, Pos};
+use crate::render::symbol;
+use crate::simulate::GameRecord;
+use std::fmt::{self, Display};
+
+/// Settings of [`annotate`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnnotateConfig {
+    /// Search of every move of positions with more empty squares than `exact_empties`.
+    pub budget: Budget,
+    /// Positions with at most this many empty squares are solved exactly, whatever it takes.
+    pub exact_empties: u32,
+    /// Least loss of a mistake and of a blunder, in discs between scores on the disc scale.
+    pub mistake_discs: i32,
+    pub blunder_discs: i32,
+    /// Least loss of a mistake and of a blunder, in evaluation points between estimates.
+    pub mistake_points: f64,
+    pub blunder_points: f64,
+}
+
+impl Default for AnnotateConfig {
+    fn default() -> Self {
+        Self {
+            budget: Budget::Nodes(200_000),
+            exact_empties: 12,
+            mistake_discs: 4,
+            blunder_discs: 10,
+            mistake_points: 20.0,
+            blunder_points: 60.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Judgement {
+    Good,
+    Mistake,
+    Blunder,
+}
+
+/// One turn of a game with what the search thinks of it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    /// Number of the turn from 1, passes included.
+    pub ply: usize,
+    pub player: Player,
+    /// The move played, `None` for a pass.
+    pub played: Option<Pos>,
+    /// Score of the move played, for the player making it.
+    pub score: Score,
+    /// The best move found and its score.
+    pub best: Option<Pos>,
+    pub best_score: Score,
+    /// Principal variation of the best move, starting with it.
+    pub pv: Vec<Option<Pos>>,
+    pub loss: Loss,
+    pub judgement: Judgement,
+}
+
+/// How much worse the move played is than the best one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Loss {
+    /// In discs, both scores being on the disc scale ([`Score::margin`]).
+    Discs(i32),
+    /// In evaluation points, both scores being estimates.
+    Points(f64),
+    /// The best move wins on the disc scale, the move played only has an estimate.
+    GivesUpWin,
+    /// The move played loses on the disc scale, the best move only has an estimate.
+    IntoLoss,
+    /// Other scores on different scales, which do not compare.
+    Unknown,
+}
+
+impl Display for Loss {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Loss::Discs(discs) => write!(f, "loses {} discs", discs),
+            Loss::Points(points) => write!(f, "loses {:.1} points", points),
+            Loss::GivesUpWin => write!(f, "gives up a win"),
+            Loss::IntoLoss => write!(f, "walks into a loss"),
+            Loss::Unknown => write!(f, "cannot be compared"),
+        }
+    }
+}
+
+/// A game with every turn annotated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnnotatedGame {
+    pub turns: Vec<Annotation>,
+    pub final_board: Board,
+}
+
+impl AnnotatedGame {
+    /// Number of mistakes and of blunders of `p`.
+    pub fn errors(&self, p: Player) -> (usize, usize) {
+        let count = |judgement| self.turns.iter().filter(|a| a.player == p && a.judgement == judgement).count();
+        (count(Judgement::Mistake), count(Judgement::Blunder))
+    }
+}
+
+fn judge(best: Score, played: Score, config: &AnnotateConfig) -> (Loss, Judgement) {
+    let (loss, mistake, blunder) = match (best, played) {
+        (Score::Estimate(best), Score::Estimate(played)) => {
+            let loss = best - played;
+            (Loss::Points(loss), loss >= config.mistake_points, loss >= config.blunder_points)
+        }
+        _ => match (best.margin(), played.margin()) {
+            (Some(best), Some(played)) => {
+                let loss = best - played;
+                (Loss::Discs(loss), loss >= config.mistake_discs, loss >= config.blunder_discs)
+            }
+            (Some(best), None) if best > 0 => (Loss::GivesUpWin, true, true),
+            (None, Some(played)) if played < 0 => (Loss::IntoLoss, true, true),
+            _ => (Loss::Unknown, false, false),
+        },
+    };
+    let judgement = match (mistake, blunder) {
+        (_, true) => Judgement::Blunder,
+        (true, false) => Judgement::Mistake,
+        (false, false) => Judgement::Good,
+    };
+    (loss, judgement)
+}
+
+/// Replays `record` from its start and evaluates every move of every turn with
+/// [`AlphaBeta`], calling `progress` after each turn.
+pub fn annotate(record: &GameRecord, config: &AnnotateConfig, mut progress: impl FnMut(&Annotation)) -> AnnotatedGame {
+    let mut evaluator = AlphaBeta::default();
+    let mut board = record.start;
+    let mut turns = Vec::new();
+    for &(p, action) in &record.turns {
+        let played = action.map(|a| a.at);
+        let next = action.map_or(board, |a| a.board);
+        let empties = 64 - board.count(Player::Black) - board.count(Player::White);
+        let budget = match empties <= config.exact_empties {
+            true => Budget::Nodes(u64::MAX),
+            false => config.budget,
+        };
+        let analyses = analysis::analyze(&board, p, &mut evaluator, budget);
+        let best = &analyses[0];
+        // a pass with legal moves is not among them
+        let score = match analyses.iter().find(|a| a.at == played) {
+            Some(analysis) => analysis.evaluation.score,
+            None => -evaluator.search(&next, p.opponent(), budget).score,
+        };
+        let (loss, judgement) = judge(best.evaluation.score, score, config);
+        let annotation = Annotation {
+            ply: turns.len() + 1,
+            player: p,
+            played,
+            score,
+            best: best.at,
+            best_score: best.evaluation.score,
+            pv: best.evaluation.pv.clone(),
+            loss,
+            judgement,
+        };
+        progress(&annotation);
+        turns.push(annotation);
+        board = next;
+    }
+    AnnotatedGame { turns, final_board: board }
+}
+
+impl Display for Annotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let played = analysis::move_name(self.played);
+        write!(f, "{:3}. {} {:4} ", self.ply, symbol(self.player), played)?;
+        let label = match self.judgement {
+            Judgement::Good => return write!(f, "{}", self.score),
+            Judgement::Mistake => "mistake",
+            Judgement::Blunder => "blunder",
+        };
+        let pv: Vec<_> = self.pv.iter().map(|&at| analysis::move_name(at)).collect();
+        write!(
+            f,
+            "{:12}  {}, {}; better {} {}: {}",
+            self.score.to_string(),
+            label,
+            self.loss,
+            analysis::move_name(self.best),
+            self.best_score,
+            pv.join(" ")
+        )
+    }
+}
+
+impl Display for AnnotatedGame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for turn in &self.turns {
+            writeln!(f, "{}", turn)?;
+        }
+        let (black, white) = (self.final_board.count(Player::Black), self.final_board.count(Player::White));
+        write!(f, "Final score {} {}-{} {}", symbol(Player::Black), black, white, symbol(Player::White))?;
+        if !self.final_board.is_final() {
+            write!(f, " (unfinished)")?;
+        }
+        for p in [Player::Black, Player::White] {
+            let (mistakes, blunders) = self.errors(p);
+            write!(f, "\n{}: {} mistakes, {} blunders", symbol(p), mistakes, blunders)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::Agent;
+    use crate::board::{Action, Position, SquareSet};
+    use crate::match_runner::openings_after;
+    use crate::simulate::simulate;
+
+    /// Plays the first legal move in reading order.
+    struct FirstMove;
+    impl Agent for FirstMove {
+        fn select_move(&mut self, board: &Board, p: Player) -> Option<Action> {
+            Pos::iter().find_map(|&pos| board.valid_moves(p).find(|a| a.at == pos))
+        }
+    }
+
+    #[test]
+    fn test_annotate() {
+        let start = openings_after(4)[3];
+        let record = simulate(start, Player::Black, &mut FirstMove, &mut FirstMove, &mut |_, _, _| {});
+        let config = AnnotateConfig {
+            budget: Budget::Nodes(2_000),
+            exact_empties: 8,
+            ..Default::default()
+        };
+        let mut seen = 0;
+        let game = annotate(&record, &config, |_| seen += 1);
+        assert_eq!(seen, game.turns.len());
+        assert_eq!(game.turns.len(), record.turns.len());
+        assert_eq!(game.final_board, record.final_board);
+        let played: Vec<_> = record.turns.iter().map(|&(p, a)| (p, a.map(|a| a.at))).collect();
+        assert_eq!(game.turns.iter().map(|a| (a.player, a.played)).collect::<Vec<_>>(), played);
+        for turn in &game.turns {
+            assert!(turn.best_score.key() >= turn.score.key());
+            assert_eq!(turn.pv.first(), Some(&turn.best));
+            assert!(!matches!(turn.score, Score::Estimate(value) if value.abs() >= 1000.0));
+        }
+        // the endgame is solved
+        assert!(matches!(game.turns.last().unwrap().score, Score::Exact(_)));
+        // such careless play makes mistakes for both sides
+        let text = game.to_string();
+        assert!(text.contains("better"), "{}", text);
+        assert!(text.contains("Final score"));
+    }
+
+    #[test]
+    fn test_annotate_passes() {
+        // Black has to pass, then White ends the game with D1
+        let start = Board::from_disks(Pos::C1.into(), SquareSet::from(Pos::A1) | Pos::B1);
+        let record: GameRecord = format!("{} D1", Position { board: start, to_move: Player::Black })
+            .parse()
+            .unwrap();
+        let game = annotate(&record, &AnnotateConfig::default(), |_| {});
+        let turns: Vec<_> = game.turns.iter().map(|a| (a.player, a.played, a.score)).collect();
+        assert_eq!(turns, vec![(Player::Black, None, Score::Exact(-4)), (Player::White, Some(Pos::D1), Score::Exact(4))]);
+        assert!(game.final_board.is_final());
+        // an unfinished game
+        let record: GameRecord = "F5D6C3".parse().unwrap();
+        let game = annotate(&record, &AnnotateConfig { budget: Budget::Nodes(1_000), ..Default::default() }, |_| {});
+        assert_eq!(game.turns.len(), 3);
+        assert!(game.to_string().contains("(unfinished)"));
+    }
+
+    #[test]
+    fn test_judge() {
+        let config = AnnotateConfig::default();
+        assert_eq!(judge(Score::Exact(6), Score::Exact(4), &config), (Loss::Discs(2), Judgement::Good));
+        assert_eq!(judge(Score::Exact(6), Score::Exact(0), &config), (Loss::Discs(6), Judgement::Mistake));
+        assert_eq!(judge(Score::Line(6), Score::Exact(-6), &config), (Loss::Discs(12), Judgement::Blunder));
+        // throwing away a win on the disc scale is a blunder whatever the estimate
+        assert_eq!(judge(Score::Exact(2), Score::Estimate(30.0), &config), (Loss::GivesUpWin, Judgement::Blunder));
+        assert_eq!(judge(Score::Estimate(-30.0), Score::Line(-2), &config), (Loss::IntoLoss, Judgement::Blunder));
+        // a drawn line against an estimate is not measured in points
+        assert_eq!(judge(Score::Estimate(50.0), Score::Exact(0), &config), (Loss::Unknown, Judgement::Good));
+        assert_eq!(judge(Score::Estimate(30.0), Score::Estimate(0.0), &config), (Loss::Points(30.0), Judgement::Mistake));
+    }
+}
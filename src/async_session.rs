@@ -0,0 +1,521 @@
+//! Client session on tokio, behind the `async` feature.
+//!
+//! The agent searches on a thread of its own while the session keeps reading from the server,
+//! so that a search can be given up when our clock runs out or the game ends under it, and a
+//! server gone silent is noticed.
+use crate::agent::{Agent, GameEnd};
+use crate::board::{Action, Board, Player};
+use crate::command::{Cmd, Event, Game, Reconnect, Session, SessionError, SessionHandler, Started};
+use std::io;
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::sync::mpsc;
+use tokio::time::{sleep_until, Instant};
+
+enum Request {
+    Initialize,
+    OpponentMove(Option<Action>),
+    Select { id: u64, board: Board, p: Player },
+    GameEnd(GameEnd),
+}
+
+type MakeAgent = Arc<dyn Fn() -> Box<dyn Agent> + Send + Sync>;
+
+/// A thread with an agent of its own, handling requests in order.
+///
+/// Dropping the worker raises the agent's stop flag (see [`Agent::set_stop`]) and closes its
+/// requests, so the thread stops as soon as the agent gives up its search.
+struct Worker {
+    requests: std::sync::mpsc::Sender<Request>,
+    replies: mpsc::UnboundedReceiver<(u64, Option<Action>)>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Worker {
+    fn start(make_agent: MakeAgent) -> Self {
+        let (requests, received) = std::sync::mpsc::channel();
+        let (replier, replies) = mpsc::unbounded_channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let agent_stop = stop.clone();
+        std::thread::spawn(move || {
+            let mut agent = make_agent();
+            agent.set_stop(agent_stop);
+            for request in received {
+                match request {
+                    Request::Initialize => agent.initialize(),
+                    Request::OpponentMove(action) => agent.opponent_move_callback(action),
+                    Request::Select { id, board, p } => {
+                        if replier.send((id, agent.select_move(&board, p))).is_err() {
+                            break;
+                        }
+                    }
+                    Request::GameEnd(end) => agent.game_end(&end),
+                }
+            }
+        });
+        Self { requests, replies, stop }
+    }
+
+    /// Asks the agent to give up its search; the thread ends once it has and the worker is gone.
+    fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// An agent searching on a thread of its own.
+///
+/// A search that is given up is told to stop through the agent's stop flag, and its thread
+/// ends once it has; a fresh agent on a new thread takes over meanwhile, so it does not have
+/// to wait for that. Dropping the searcher stops the thread the same way. Agents that ignore
+/// the flag finish their search alone before their thread ends.
+pub struct Searcher {
+    make_agent: MakeAgent,
+    worker: Worker,
+    last_id: u64,
+}
+
+impl Searcher {
+    /// Starts the thread and builds the agent there with `make_agent`, so that the agent
+    /// itself need not be `Send`. A new agent is made whenever a search is given up.
+    pub fn spawn<A: Agent + 'static>(make_agent: impl Fn() -> A + Send + Sync + 'static) -> Self {
+        let make_agent: MakeAgent = Arc::new(move || Box::new(make_agent()));
+        Self {
+            worker: Worker::start(make_agent.clone()),
+            make_agent,
+            last_id: 0,
+        }
+    }
+
+    fn send(&self, request: Request) {
+        // the thread only stops before us if the agent panicked, which `result` reports
+        let _ = self.worker.requests.send(request);
+    }
+
+    /// Starts searching `board` for `p`; results of earlier searches are ignored from now on.
+    fn select(&mut self, board: Board, p: Player) -> u64 {
+        self.last_id += 1;
+        self.send(Request::Select {
+            id: self.last_id,
+            board,
+            p,
+        });
+        self.last_id
+    }
+
+    /// Stops the search in progress and carries on with a fresh agent, ready for a game, so
+    /// that the next request is not stuck behind it.
+    fn give_up(&mut self) {
+        self.worker.stop();
+        self.worker = Worker::start(self.make_agent.clone());
+        self.send(Request::Initialize);
+    }
+
+    /// Result of search `id`, `None` if the thread stopped.
+    async fn result(&mut self, id: u64) -> Option<Option<Action>> {
+        while let Some((got, action)) = self.worker.replies.recv().await {
+            if got == id {
+                return Some(action);
+            }
+        }
+        None
+    }
+}
+
+/// Settings of the async session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AsyncOptions {
+    /// Time kept in reserve: a search still running this close to the end of our clock is
+    /// given up, and the first legal move sent instead.
+    pub safety_margin: Duration,
+    /// How long the server may stay silent while it is not waiting for our move, `None` to
+    /// wait forever.
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for AsyncOptions {
+    fn default() -> Self {
+        Self {
+            safety_margin: Duration::from_millis(200),
+            idle_timeout: None,
+        }
+    }
+}
+
+impl AsyncOptions {
+    pub fn safety_margin(mut self, margin: Duration) -> Self {
+        self.safety_margin = margin;
+        self
+    }
+
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+}
+
+enum Wakeup {
+    Line(Option<String>),
+    Searched(Option<Option<Action>>),
+    Timeout,
+}
+
+impl Session {
+    /// Async counterpart of [`Session::launch_with`], `searcher` playing.
+    pub async fn launch_async(
+        &mut self,
+        searcher: &mut Searcher,
+        stream: TcpStream,
+        options: &AsyncOptions,
+        handler: &mut impl SessionHandler,
+    ) -> Result<(), SessionError> {
+        stream.set_nodelay(true)?;
+        let (reader, writer) = stream.into_split();
+        self.run_async(searcher, BufReader::new(reader), BufWriter::new(writer), options, handler)
+            .await
+    }
+
+    /// Async counterpart of [`Session::launch_reconnecting`], connecting to `addr`.
+    pub async fn launch_async_reconnecting(
+        &mut self,
+        searcher: &mut Searcher,
+        addr: impl ToSocketAddrs + Clone,
+        policy: &Reconnect,
+        options: &AsyncOptions,
+        handler: &mut impl SessionHandler,
+    ) -> Result<(), SessionError> {
+        let mut attempt = 0;
+        loop {
+            let mut started = Started(false);
+            let result = match TcpStream::connect(addr.clone()).await {
+                Ok(stream) => {
+                    self.launch_async(searcher, stream, options, &mut (&mut *handler, &mut started))
+                        .await
+                }
+                Err(err) => Err(err.into()),
+            };
+            match self.retry(policy, &mut attempt, started.0, result, handler) {
+                ControlFlow::Continue(delay) => tokio::time::sleep(delay).await,
+                ControlFlow::Break(result) => return result,
+            }
+        }
+    }
+
+    /// Async counterpart of [`Session::run_with`]: plays until the server says `BYE`, with
+    /// `searcher` thinking while commands keep being read.
+    ///
+    /// A search is given up when our clock is about to run out, the first legal move being
+    /// sent instead, and when the game ends before it does; a fresh agent then plays on.
+    pub async fn run_async(
+        &mut self,
+        searcher: &mut Searcher,
+        reader: impl AsyncBufRead + Unpin,
+        mut writer: impl AsyncWrite + Unpin,
+        options: &AsyncOptions,
+        handler: &mut impl SessionHandler,
+    ) -> Result<(), SessionError> {
+        let mut lines = reader.lines();
+        let open = Cmd::Open {
+            name: self.self_name.clone(),
+        };
+        self.write_async(&mut writer, &open).await?;
+        handler.on_open(&self.self_name);
+        searcher.send(Request::Initialize);
+        let mut game: Option<Game> = None;
+        let mut remaining = Duration::ZERO;
+        // the search we wait for, and when it has to be answered by
+        let mut thinking: Option<(u64, Instant)> = None;
+        loop {
+            if game.as_ref().is_none_or(|g| g.abandoned) && thinking.take().is_some() {
+                searcher.give_up();
+            }
+            let timeout = match thinking {
+                Some((_, deadline)) => Some(deadline),
+                None => options.idle_timeout.map(|t| Instant::now() + t),
+            };
+            let id = thinking.map_or(0, |(id, _)| id);
+            let wakeup = tokio::select! {
+                line = lines.next_line() => Wakeup::Line(line?),
+                result = searcher.result(id), if thinking.is_some() => Wakeup::Searched(result),
+                _ = sleep_until(timeout.unwrap_or_else(Instant::now)), if timeout.is_some() => Wakeup::Timeout,
+            };
+            let line = match wakeup {
+                Wakeup::Line(Some(line)) => line,
+                Wakeup::Line(None) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                Wakeup::Searched(None) => return Err(io::Error::other("the search thread stopped").into()),
+                Wakeup::Searched(Some(action)) => {
+                    thinking = None;
+                    let current = game.as_mut().expect("game in progress");
+                    self.play_async(current, action, &mut writer, handler).await?;
+                    continue;
+                }
+                Wakeup::Timeout if thinking.is_some() => {
+                    thinking = None;
+                    searcher.give_up();
+                    let current = game.as_mut().expect("game in progress");
+                    eprintln!("[{}] out of time, playing the first legal move", self.self_name);
+                    let action = current.board.valid_moves(current.bw).next();
+                    self.play_async(current, action, &mut writer, handler).await?;
+                    continue;
+                }
+                Wakeup::Timeout => {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "the server went silent").into());
+                }
+            };
+            let cmd = Cmd::try_from(line.as_str());
+            if let (Ok(Cmd::Move(_)), Some(_)) = (&cmd, thinking) {
+                // not the opponent's turn
+                self.recover(SessionError::UnexpectedCmd(cmd.expect("a move")), &mut game)?;
+                continue;
+            }
+            match self.handle(cmd, &mut game, handler)? {
+                Event::Start { restart, remaining_time } => {
+                    remaining = Duration::from_millis(remaining_time.0.into());
+                    if restart {
+                        match thinking.take() {
+                            Some(_) => searcher.give_up(),
+                            None => searcher.send(Request::Initialize),
+                        }
+                    }
+                    let current = game.as_ref().expect("game in progress");
+                    if current.bw == Player::Black {
+                        thinking = Some(think(searcher, current.board, current.bw, remaining, options));
+                    }
+                }
+                Event::OpponentMove(action) => {
+                    let current = game.as_ref().expect("game in progress");
+                    searcher.send(Request::OpponentMove(action));
+                    thinking = Some(think(searcher, current.board, current.bw, remaining, options));
+                }
+                Event::Ack(remaining_time) => remaining = Duration::from_millis(remaining_time.0.into()),
+                Event::End(end) => match thinking.take() {
+                    // the fresh agent is ready for the next game
+                    Some(_) => searcher.give_up(),
+                    None => {
+                        searcher.send(Request::GameEnd(end));
+                        searcher.send(Request::Initialize);
+                    }
+                },
+                Event::Bye => return Ok(()),
+                Event::Skip => {}
+            }
+        }
+    }
+
+    async fn write_async(&self, writer: &mut (impl AsyncWrite + Unpin), cmd: &Cmd) -> Result<(), SessionError> {
+        writer.write_all(format!("{}\n", cmd).as_bytes()).await?;
+        writer.flush().await?;
+        eprintln!("[{}] 发 {}", self.self_name, cmd);
+        Ok(())
+    }
+
+    /// Plays `action` in `game` and sends it.
+    async fn play_async(
+        &self,
+        game: &mut Game,
+        action: Option<Action>,
+        writer: &mut (impl AsyncWrite + Unpin),
+        handler: &mut impl SessionHandler,
+    ) -> Result<(), SessionError> {
+        if let Some(action) = action {
+            game.board = action.board;
+        }
+        let pos = action.map(|a| a.at);
+        self.write_async(writer, &Cmd::Move(pos)).await?;
+        handler.on_move(game.bw, pos, &game.board);
+        Ok(())
+    }
+}
+
+/// Starts searching `board` for `p`, to be answered before `remaining` runs out.
+fn think(searcher: &mut Searcher, board: Board, p: Player, remaining: Duration, options: &AsyncOptions) -> (u64, Instant) {
+    let deadline = Instant::now() + remaining.saturating_sub(options.safety_margin);
+    (searcher.select(board, p), deadline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::RandomAgent;
+
+    /// Plays the last legal move after thinking for a while.
+    struct Slow(Duration);
+    impl Agent for Slow {
+        fn select_move(&mut self, board: &Board, p: Player) -> Option<Action> {
+            std::thread::sleep(self.0);
+            board.valid_moves(p).last()
+        }
+    }
+
+    enum Step {
+        Send(String),
+        Expect(String),
+    }
+    use Step::*;
+
+    /// Runs a session with `searcher` against a server following `script`, checking that the
+    /// session writes nothing more than expected.
+    async fn run_script(mut searcher: Searcher, options: AsyncOptions, script: Vec<Step>) -> Result<(), SessionError> {
+        let (client, server) = tokio::io::duplex(4096);
+        let server = tokio::spawn(async move {
+            let (reader, mut writer) = tokio::io::split(server);
+            let mut lines = BufReader::new(reader).lines();
+            assert_eq!(lines.next_line().await.unwrap().unwrap(), "OPEN me");
+            for step in script {
+                match step {
+                    Send(line) => writer.write_all(format!("{}\n", line).as_bytes()).await.unwrap(),
+                    Expect(prefix) => {
+                        let line = lines.next_line().await.unwrap().unwrap();
+                        assert!(line.starts_with(&prefix), "expected {:?}, got {:?}", prefix, line);
+                    }
+                }
+            }
+            assert_eq!(lines.next_line().await.unwrap(), None);
+        });
+        let (reader, writer) = tokio::io::split(client);
+        let result = Session::new("me")
+            .run_async(&mut searcher, BufReader::new(reader), writer, &options, &mut ())
+            .await;
+        server.await.unwrap();
+        result
+    }
+
+    #[tokio::test]
+    async fn test_game() {
+        let result = run_script(Searcher::spawn(|| RandomAgent), AsyncOptions::default(), vec![
+            Send("START WHITE bob 60000".into()),
+            Send("MOVE F5".into()),
+            Expect("MOVE ".into()),
+            Send("ACK 59000".into()),
+            Send("END LOSE 3 61 TIMEOUT".into()),
+            Send("BYE".into()),
+        ])
+        .await;
+        assert!(result.is_ok(), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn test_out_of_time() {
+        let first = Board::initial().valid_moves(Player::Black).next().unwrap().at;
+        let start = std::time::Instant::now();
+        let options = AsyncOptions::default().safety_margin(Duration::from_millis(100));
+        let result = run_script(Searcher::spawn(|| Slow(Duration::from_secs(2))), options, vec![
+            Send("START BLACK bob 300".into()),
+            Expect(format!("MOVE {}", first)),
+            Send("END LOSE 1 4 TIMEOUT".into()),
+            Send("BYE".into()),
+        ])
+        .await;
+        assert!(result.is_ok(), "{:?}", result);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    /// Thinks for long on the first search of any of its instances, later plays the last
+    /// legal move at once.
+    struct SlowFirst(Arc<std::sync::atomic::AtomicBool>);
+    impl Agent for SlowFirst {
+        fn select_move(&mut self, board: &Board, p: Player) -> Option<Action> {
+            if !self.0.swap(true, std::sync::atomic::Ordering::Relaxed) {
+                std::thread::sleep(Duration::from_secs(3));
+            }
+            board.valid_moves(p).last()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_after_timeout() {
+        let first = Board::initial().valid_moves(Player::Black).next().unwrap();
+        let reply = first.board.valid_moves(Player::White).next().unwrap();
+        let searched = reply.board.valid_moves(Player::Black).last().unwrap().at;
+        assert_ne!(Some(searched), reply.board.valid_moves(Player::Black).next().map(|a| a.at));
+        let slow = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let start = std::time::Instant::now();
+        let options = AsyncOptions::default().safety_margin(Duration::from_millis(100));
+        let result = run_script(Searcher::spawn(move || SlowFirst(slow.clone())), options, vec![
+            Send("START BLACK bob 300".into()),
+            Expect(format!("MOVE {}", first.at)),
+            Send("ACK 1000".into()),
+            Send(format!("MOVE {}", reply.at)),
+            // searched by a fresh agent, not stuck behind the search given up
+            Expect(format!("MOVE {}", searched)),
+            Send("END LOSE 1 4 ABORTED".into()),
+            Send("BYE".into()),
+        ])
+        .await;
+        assert!(result.is_ok(), "{:?}", result);
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    /// Searches until it is told to stop, and counts the instances dropped, i.e. whose thread
+    /// has ended.
+    struct UntilStopped {
+        stop: Arc<AtomicBool>,
+        dropped: Arc<std::sync::atomic::AtomicUsize>,
+    }
+    impl Agent for UntilStopped {
+        fn set_stop(&mut self, stop: Arc<AtomicBool>) {
+            self.stop = stop;
+        }
+        fn select_move(&mut self, board: &Board, p: Player) -> Option<Action> {
+            while !self.stop.load(Ordering::Relaxed) {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            board.valid_moves(p).next()
+        }
+    }
+    impl Drop for UntilStopped {
+        fn drop(&mut self) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_give_up_stops_thread() {
+        let dropped = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = dropped.clone();
+        let mut searcher = Searcher::spawn(move || UntilStopped {
+            stop: Arc::default(),
+            dropped: counter.clone(),
+        });
+        searcher.select(Board::initial(), Player::Black);
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(dropped.load(Ordering::Relaxed), 0);
+        searcher.give_up();
+        let start = std::time::Instant::now();
+        while dropped.load(Ordering::Relaxed) == 0 && start.elapsed() < Duration::from_secs(2) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        // the abandoned thread has ended, the fresh one has not
+        assert_eq!(dropped.load(Ordering::Relaxed), 1);
+        drop(searcher);
+    }
+
+    #[tokio::test]
+    async fn test_end_cancels_search() {
+        let start = std::time::Instant::now();
+        let result = run_script(Searcher::spawn(|| Slow(Duration::from_secs(2))), AsyncOptions::default(), vec![
+            Send("START BLACK bob 60000".into()),
+            Send("END LOSE 0 0 ABORTED".into()),
+            Send("BYE".into()),
+        ])
+        .await;
+        assert!(result.is_ok(), "{:?}", result);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout() {
+        let options = AsyncOptions::default().idle_timeout(Duration::from_millis(100));
+        let result = run_script(Searcher::spawn(|| RandomAgent), options, vec![]).await;
+        assert!(matches!(result, Err(SessionError::IoError(e)) if e.kind() == io::ErrorKind::TimedOut));
+    }
+}
@@ -1,5 +1,6 @@
-use core::panic;
-use std::fmt::{Display, write};
+use std::fmt::Display;
+pub use crate::square_set::SquareSet;
+use crate::movegen;
 
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
@@ -32,79 +33,139 @@ pub enum Pos{
 }
 
 
+static POSITIONS: [Pos; 64] = {
+    use Pos::*;
+    [
+        A1, B1, C1, D1, E1, F1, G1, H1,
+        A2, B2, C2, D2, E2, F2, G2, H2,
+        A3, B3, C3, D3, E3, F3, G3, H3,
+        A4, B4, C4, D4, E4, F4, G4, H4,
+        A5, B5, C5, D5, E5, F5, G5, H5,
+        A6, B6, C6, D6, E6, F6, G6, H6,
+        A7, B7, C7, D7, E7, F7, G7, H7,
+        A8, B8, C8, D8, E8, F8, G8, H8,
+    ]
+};
+
 impl Pos {
+    /// Iterates over all 64 squares in reading order (A1, B1, ..., H8).
     pub fn iter() -> std::slice::Iter<'static, Pos> {
-        use Pos::*;
-        static POSTIONS: [Pos; 64] = [
-            A1, B1, C1, D1, E1, F1, G1, H1,
-            A2, B2, C2, D2, E2, F2, G2, H2,
-            A3, B3, C3, D3, E3, F3, G3, H3,
-            A4, B4, C4, D4, E4, F4, G4, H4,
-            A5, B5, C5, D5, E5, F5, G5, H5,
-            A6, B6, C6, D6, E6, F6, G6, H6,
-            A7, B7, C7, D7, E7, F7, G7, H7,
-            A8, B8, C8, D8, E8, F8, G8, H8,
-        ];
-        POSTIONS.iter()
+        POSITIONS.iter()
+    }
+    /// Returns the square whose bit index (its discriminant) is `index`,
+    /// or `None` if `index >= 64`.
+    pub fn from_index(index: u8) -> Option<Pos> {
+        if index < 64 {
+            Some(POSITIONS[63 - index as usize])
+        } else {
+            None
+        }
+    }
+    /// Returns the square at `row` (0 is rank 1) and `col` (0 is file A).
+    pub fn from_row_col(row: u8, col: u8) -> Option<Pos> {
+        if row < 8 && col < 8 {
+            Some(POSITIONS[(row * 8 + col) as usize])
+        } else {
+            None
+        }
+    }
+    /// Returns the square of the only bit set in `mask`, or `None` if `mask`
+    /// does not have exactly one bit set.
+    pub fn from_mask(mask: u64) -> Option<Pos> {
+        if mask.count_ones() == 1 {
+            Pos::from_index(mask.trailing_zeros() as u8)
+        } else {
+            None
+        }
+    }
+    /// Iterates over the squares of the bits set in `mask`, from the lowest bit (H8) upwards.
+    pub fn iter_mask(mask: u64) -> PosIter {
+        PosIter(mask)
+    }
+    /// Bit index of the square, the same as `self as u8`.
+    pub fn index(self) -> u8 {
+        self as u8
+    }
+    /// Row of the square, 0 for rank 1 and 7 for rank 8.
+    pub fn row(self) -> u8 {
+        (63 - self as u8) / 8
+    }
+    /// Column of the square, 0 for file A and 7 for file H.
+    pub fn col(self) -> u8 {
+        (63 - self as u8) % 8
+    }
+    /// The single-bit mask of the square.
+    pub fn mask(self) -> u64 {
+        MASK << self as u64
     }
     pub fn from_wthor_position(pos: &wthor::Position) -> Self{
         use wthor::Position;
         let &Position { rank, file } = pos;
-        assert!(rank < 8);
-        assert!(file < 8);
-        let a = match rank {
-            0 => 63,
-            1 => 55,
-            2 => 47,
-            3 => 39,
-            4 => 31,
-            5 => 23,
-            6 => 15,
-            7 => 7,
-            _ => panic!("Invalid rank: {}", rank),
-        } - file;
-        unsafe { std::mem::transmute(a) }
+        Pos::from_row_col(rank, file)
+            .unwrap_or_else(|| panic!("Invalid position: rank={}, file={}", rank, file))
+    }
+}
+
+impl From<Pos> for u64 {
+    fn from(pos: Pos) -> u64 {
+        pos.mask()
+    }
+}
+
+/// Iterator over the squares of the bits set in a `u64`, created by [`Pos::iter_mask`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct PosIter(u64);
+
+impl Iterator for PosIter {
+    type Item = Pos;
+    fn next(&mut self) -> Option<Pos> {
+        if self.0 == 0 {
+            return None;
+        }
+        let i = self.0.trailing_zeros() as u8;
+        self.0 &= self.0 - 1;
+        Pos::from_index(i)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.0.count_ones() as usize;
+        (n, Some(n))
     }
 }
+impl ExactSizeIterator for PosIter {}
+impl std::iter::FusedIterator for PosIter {}
+
 impl Display for Pos {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self)
     }
 }
 #[derive(thiserror::Error, Clone, PartialEq, Eq, Hash, Debug)]
-#[error("{0}: expected [A-H][1-8]")]
+#[error("{0}: expected [A-Ha-h][1-8]")]
 pub struct ParsePosError(String);
 
 impl TryFrom<&str> for Pos {
     type Error = ParsePosError;
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        let mut res = 0u8;
-        if value.len() != 2 || !value.is_ascii() {
-            return Err(ParsePosError(value.to_string()));
-        }
-        match value.get(1..2) {
-            Some("1") => res += 63,
-            Some("2") => res += 55,
-            Some("3") => res += 47,
-            Some("4") => res += 39,
-            Some("5") => res += 31,
-            Some("6") => res += 23,
-            Some("7") => res += 15,
-            Some("8") => res += 7,
-            _ => return Err(ParsePosError(value.to_string())),
+        let err = || ParsePosError(value.to_string());
+        let &[file, rank] = value.as_bytes() else {
+            return Err(err());
+        };
+        let col = match file {
+            b'A'..=b'H' => file - b'A',
+            b'a'..=b'h' => file - b'a',
+            _ => return Err(err()),
         };
-        match value.get(0..1) {
-            Some("A") => res -= 0,
-            Some("B") => res -= 1,
-            Some("C") => res -= 2,
-            Some("D") => res -= 3,
-            Some("E") => res -= 4,
-            Some("F") => res -= 5,
-            Some("G") => res -= 6,
-            Some("H") => res -= 7,
-            _ => return Err(ParsePosError(value.to_string())),
+        let row = match rank {
+            b'1'..=b'8' => rank - b'1',
+            _ => return Err(err()),
         };
-        return unsafe { Ok(std::mem::transmute(res)) }
+        Pos::from_row_col(row, col).ok_or_else(err)
+    }
+}
+impl std::str::FromStr for Pos {
+    type Err = ParsePosError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Pos::try_from(s)
     }
 }
 
@@ -127,66 +188,149 @@ mod tests{
     fn test_from_wthor_position_panic() {
         Pos::from_wthor_position(&wthor::Position { rank: 8, file: 0 });
     }
+    #[test]
+    fn test_from_index() {
+        for &pos in Pos::iter() {
+            assert_eq!(Pos::from_index(pos as u8), Some(pos));
+            assert_eq!(Pos::from_row_col(pos.row(), pos.col()), Some(pos));
+            assert_eq!(Pos::from_mask(pos.mask()), Some(pos));
+        }
+        assert_eq!(Pos::from_index(64), None);
+        assert_eq!(Pos::from_row_col(0, 8), None);
+        assert_eq!(Pos::from_mask(0), None);
+        assert_eq!(Pos::from_mask(0b11), None);
+        assert_eq!((Pos::C4.row(), Pos::C4.col()), (3, 2));
+    }
+    #[test]
+    fn test_parse() {
+        for &pos in Pos::iter() {
+            let s = pos.to_string();
+            assert_eq!(s.parse::<Pos>(), Ok(pos));
+            assert_eq!(s.to_lowercase().parse::<Pos>(), Ok(pos));
+        }
+        for s in ["", "A", "A0", "A9", "I1", "1A", "A1 ", "A10", "Ａ1"] {
+            assert!(s.parse::<Pos>().is_err(), "{:?}", s);
+        }
+    }
+    #[test]
+    fn test_position() {
+        let initial = Position { board: Board::initial(), to_move: Player::Black };
+        let text = "---------------------------OX------XO--------------------------- X";
+        assert_eq!(initial.to_string(), text);
+        assert_eq!(text.parse(), Ok(initial));
+        let grid = "........\n........\n........\n...ox...\n...xo...\n........\n........\n........\nx";
+        assert_eq!(grid.parse(), Ok(initial));
+        assert_eq!("X".parse::<Position>(), Err(ParsePositionError::Length(1)));
+        assert_eq!(text.replace('X', "Y").parse::<Position>(), Err(ParsePositionError::Square('Y')));
+        assert_eq!(text.replace(" X", " B").parse::<Position>(), Err(ParsePositionError::ToMove('B')));
+    }
+    #[test]
+    fn test_iter_mask() {
+        assert_eq!(Pos::iter_mask(0).next(), None);
+        let positions: Vec<_> = Pos::iter_mask(Pos::A1.mask() | Pos::D5.mask() | Pos::H8.mask()).collect();
+        assert_eq!(positions, vec![Pos::H8, Pos::D5, Pos::A1]);
+        assert_eq!(Pos::iter_mask(u64::MAX).len(), 64);
+    }
+    #[test]
+    fn test_symmetries() {
+        use rand::seq::IteratorRandom;
+        let mut rng = rand::thread_rng();
+        let mut board = Board::initial();
+        let mut p = Player::Black;
+        while !board.is_final() {
+            for b in board.symmetries() {
+                assert_eq!(b.canonical(), board.canonical());
+                assert_eq!(b.moves(p).len(), board.moves(p).len());
+                assert_eq!(b.count(Player::Black), board.count(Player::Black));
+            }
+            if let Some(action) = board.valid_moves(p).choose(&mut rng) {
+                board = action.board;
+            }
+            p.flip();
+        }
+        let images: std::collections::HashSet<_> = Board::from_disks(Pos::B3.into(), SquareSet::EMPTY).symmetries().into();
+        assert_eq!(images.len(), 8);
+        let corner = Board::from_disks(Pos::A1.into(), SquareSet::EMPTY).symmetries();
+        let corners: SquareSet = corner.iter().map(|b| b.get_disks(Player::Black).first().unwrap()).collect();
+        assert_eq!(corners, SquareSet::CORNERS);
+    }
+    #[test]
+    fn test_flips() {
+        let board = Board::initial();
+        assert_eq!(board.flips(Player::Black, Pos::F5), SquareSet::from(Pos::E5));
+        assert_eq!(board.flips(Player::Black, Pos::D4), SquareSet::EMPTY);
+        assert_eq!(board.flips(Player::Black, Pos::A1), SquareSet::EMPTY);
+        let moves: Vec<_> = board.moves(Player::Black).collect();
+        assert_eq!(moves, vec![Pos::E6, Pos::F5, Pos::C4, Pos::D3]);
+    }
+    #[test]
+    fn test_make_unmake_move() {
+        use rand::seq::IteratorRandom;
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let mut board = Board::initial();
+            let mut p = Player::Black;
+            let mut history = Vec::new();
+            while !board.is_final() {
+                let actions: Vec<_> = board.valid_moves(p).collect();
+                let moves: Vec<_> = board.moves(p).collect();
+                assert_eq!(moves, actions.iter().map(|a| a.at).collect::<Vec<_>>());
+                if let Some(action) = actions.into_iter().choose(&mut rng) {
+                    let before = board;
+                    let mv = board.make_move(p, action.at).unwrap();
+                    assert_eq!(board, action.board);
+                    assert_eq!(mv.flips, before.get_disks(p.opponent()) - board.get_disks(p.opponent()));
+                    history.push((p, before, mv));
+                }
+                p.flip();
+            }
+            while let Some((p, before, mv)) = history.pop() {
+                board.unmake_move(p, mv);
+                assert_eq!(board, before);
+            }
+            assert_eq!(board, Board::initial());
+        }
+    }
 }
 
-fn north(xy: &Pos) -> Option<Pos> {
-    use Pos::*;
-    match xy {
-        A1 | B1 | C1 | D1 | E1 | F1 | G1 | H1 => None,
-        _ => unsafe {std::mem::transmute(*xy as u8 + 8)},
+impl Pos {
+    fn offset(self, d_row: i8, d_col: i8) -> Option<Pos> {
+        let row = self.row() as i8 + d_row;
+        let col = self.col() as i8 + d_col;
+        if (0..8).contains(&row) && (0..8).contains(&col) {
+            Pos::from_row_col(row as u8, col as u8)
+        } else {
+            None
+        }
     }
 }
+fn north(xy: &Pos) -> Option<Pos> {
+    xy.offset(-1, 0)
+}
 fn south(xy: &Pos) -> Option<Pos> {
-    use Pos::*;
-    match xy {
-        A8 | B8 | C8 | D8 | E8 | F8 | G8 | H8 => None,
-        _ => unsafe {std::mem::transmute(*xy as u8 - 8)},
-    }
+    xy.offset(1, 0)
 }
 fn east(xy: &Pos) -> Option<Pos> {
-    use Pos::*;
-    match xy {
-        H1 | H2 | H3 | H4 | H5 | H6 | H7 | H8 => None,
-        _ => unsafe {std::mem::transmute(*xy as u8 - 1)},
-    }
+    xy.offset(0, 1)
 }
 fn west(xy: &Pos) -> Option<Pos> {
-    use Pos::*;
-    match xy {
-        A1 | A2 | A3 | A4 | A5 | A6 | A7 | A8 => None,
-        _ => unsafe {std::mem::transmute(*xy as u8 + 1)},
-    }
+    xy.offset(0, -1)
 }
 fn northwest(xy: &Pos) -> Option<Pos> {
-    use Pos::*;
-    match xy {
-        A1 | B1 | C1 | D1 | E1 | F1 | G1 | H1 | A2 | A3 | A4 | A5 | A6 | A7 | A8 => None,
-        _ => unsafe {std::mem::transmute(*xy as u8 + 9)},
-    }
+    xy.offset(-1, -1)
 }
 fn southeast(xy: &Pos) -> Option<Pos> {
-    use Pos::*;
-    match xy {
-        H8 | G8 | F8 | E8 | D8 | C8 | B8 | A8 | H7 | H6 | H5 | H4 | H3 | H2 | H1 => None,
-        _ => unsafe {std::mem::transmute(*xy as u8 - 9)},
-    }
+    xy.offset(1, 1)
 }
 fn northeast(xy: &Pos) -> Option<Pos> {
-    use Pos::*;
-    match xy {
-        H1 | G1 | F1 | E1 | D1 | C1 | B1 | A1 | H2 | H3 | H4 | H5 | H6 | H7 | H8 => None,
-        _ => unsafe {std::mem::transmute(*xy as u8 + 7)},
-    }
+    xy.offset(-1, 1)
 }
 fn southwest(xy: &Pos) -> Option<Pos> {
-    use Pos::*;
-    match xy {
-        A8 | B8 | C8 | D8 | E8 | F8 | G8 | H8 | A7 | A6 | A5 | A4 | A3 | A2 | A1 => None,
-        _ => unsafe {std::mem::transmute(*xy as u8 - 7)},
-    }
+    xy.offset(1, -1)
 }
 
-enum CompassRose {
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum CompassRose {
     North,
     South,
     East,
@@ -198,7 +342,7 @@ enum CompassRose {
 }
 
 impl CompassRose {
-    fn iter() -> std::slice::Iter<'static, CompassRose> {
+    pub fn iter() -> std::slice::Iter<'static, CompassRose> {
         use CompassRose::*;
         static COMPASS_ROSE: [CompassRose; 8] = [
             North, South, East, West, Northwest, Southeast, Northeast, Southwest,
@@ -227,7 +371,7 @@ impl CompassRose {
 pub struct Board(u64, u64);
 const MASK: u64 = 0x1;
 
-trait Shift {
+pub(crate) trait Shift {
     fn shift(&self, d: &CompassRose) -> Self;
 }
 impl Shift for u64 {
@@ -280,6 +424,15 @@ fn flip_diag_a8_h1(mut x: u64) -> u64 {
     x
 }
 
+fn flip_horizontal(x: u64) -> u64 {
+    const H1: u64 = 0x5555_5555_5555_5555;
+    const H2: u64 = 0x3333_3333_3333_3333;
+    const H4: u64 = 0x0F0F_0F0F_0F0F_0F0F;
+    let x = ((x >> 1) & H1) | ((x & H1) << 1);
+    let x = ((x >> 2) & H2) | ((x & H2) << 2);
+    ((x >> 4) & H4) | ((x & H4) << 4)
+}
+
 fn rotate180(x: u64) -> u64 {
     const H1: u64 = 0x5555_5555_5555_5555;
     const H2: u64 = 0x3333_3333_3333_3333;
@@ -291,10 +444,8 @@ fn rotate180(x: u64) -> u64 {
     let x = ((x >> 4) & H4) | ((x & H4) << 4);
     let x = ((x >> 8) & V1) | ((x & V1) << 8);
     let x = ((x >> 16) & V2) | ((x & V2) << 16);
-    let x = (x >> 32) | (x << 32);
-    x
+    x.rotate_left(32)
 }
-fn id<T>(x: T) -> T {x}
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct Action {
     pub at: Pos,
@@ -308,12 +459,12 @@ impl Board {
             0b_00000000_00000000_00000000_00010000_00001000_00000000_00000000_00000000,
         )
     }
-    pub fn from_disks(black: u64, white: u64) -> Board {
-        assert!(black & white == 0);
-        Board(black, white)
+    pub fn from_disks(black: SquareSet, white: SquareSet) -> Board {
+        assert!(black.is_disjoint(white));
+        Board(black.bits(), white.bits())
     }
     pub fn is_final(&self) -> bool {
-        self.valid_moves_fast(Player::Black) == 0 && self.valid_moves_fast(Player::White) == 0
+        self.valid_moves_fast(Player::Black).is_empty() && self.valid_moves_fast(Player::White).is_empty()
     }
     pub fn count(&self, p: Player) -> u32 {
         match p {
@@ -334,7 +485,8 @@ impl Board {
             },
         }
     }
-    pub fn flip_discs(&self, disc_set: u64) -> Board {
+    pub fn flip_discs(&self, disc_set: SquareSet) -> Board {
+        let disc_set = disc_set.bits();
         if (self.0 | self.1) & disc_set == disc_set {
             return Board(self.0 ^ disc_set, self.1 ^ disc_set);
         }
@@ -354,7 +506,14 @@ impl Board {
         }
         None
     }
-    pub fn get_disks(&self, p: Player) -> u64 {
+    pub fn get_disks(&self, p: Player) -> SquareSet {
+        SquareSet::new(self.disks(p))
+    }
+    /// Squares not occupied by either player.
+    pub fn empty_squares(&self) -> SquareSet {
+        SquareSet::new(!(self.0 | self.1))
+    }
+    fn disks(&self, p: Player) -> u64 {
         match p {
             Player::Black => self.0,
             Player::White => self.1,
@@ -369,25 +528,35 @@ impl Board {
     pub fn rotate180(&self) -> Board {
         Board(rotate180(self.0), rotate180(self.1))
     }
-    fn equivalent(&self, other: &Board) -> bool {
-        if self == other {
-            return true;
-        }
-        if self.flip_diag_a1_h8() == *other {
-            return true;
-        }
-        if self.flip_diag_a8_h1() == *other {
-            return true;
-        }
-        if self.rotate180() == *other {
-            return true;
-        }
-        false
+    /// Mirrors the board top to bottom (rank 1 and rank 8 swap).
+    pub fn flip_vertical(&self) -> Board {
+        Board(self.0.swap_bytes(), self.1.swap_bytes())
+    }
+    /// Mirrors the board left to right (file A and file H swap).
+    pub fn flip_horizontal(&self) -> Board {
+        Board(flip_horizontal(self.0), flip_horizontal(self.1))
+    }
+    /// The images of the board under the 8 symmetries of the square, identity first.
+    pub fn symmetries(&self) -> [Board; 8] {
+        let d = self.flip_diag_a1_h8();
+        [
+            *self,
+            self.rotate180(),
+            self.flip_vertical(),
+            self.flip_horizontal(),
+            d,
+            self.flip_diag_a8_h1(),
+            d.flip_vertical(),
+            d.flip_horizontal(),
+        ]
+    }
+    /// A representative of the board's symmetry class: the same for all 8 of [`Board::symmetries`].
+    pub fn canonical(&self) -> Board {
+        self.symmetries().into_iter().min_by_key(|b| (b.0, b.1)).unwrap()
     }
-
     pub fn valid_moves_slow(&self, p: Player) -> impl Iterator<Item = Action> + '_ {
         Pos::iter().filter_map(move|xy| {
-            if let None = self.get(xy) {
+            if self.get(xy).is_none() {
                 let mut flip_candidate = 0u64;
                 for dir in CompassRose::iter() {
                     let mut b = 0u64;
@@ -406,136 +575,130 @@ impl Board {
                     }
                 }
                 if flip_candidate != 0 {
-                    let res = Some(Action{ at: *xy, board: self.set(*xy, p).unwrap().flip_discs(flip_candidate)});
-                    return res;
+                    return Some(Action{ at: *xy, board: self.set(*xy, p).unwrap().flip_discs(SquareSet::new(flip_candidate))});
                 }
             }
-            return None;
+            None
         })
     }
     pub fn place_at_unchecked(&self, p: Player, i: Pos) -> Board {
-            let my_disks = self.get_disks(p) | (MASK << i as u64);
-            let opp_disks = self.get_disks(p.opponent());
-            let mut captured_disks = 0u64;
-            for d in CompassRose::iter() {
-                let x = (MASK << i as u64).shift(d) & opp_disks;
-                let x = x | (x.shift(d) & opp_disks);
-                let x = x | (x.shift(d) & opp_disks);
-                let x = x | (x.shift(d) & opp_disks);
-                let x = x | (x.shift(d) & opp_disks);
-                let x = x | (x.shift(d) & opp_disks);
-                if x.shift(d) & my_disks != 0 {
-                    captured_disks |= x; 
-                }
-            }
+            let my_disks = self.disks(p) | i.mask();
+            let opp_disks = self.disks(p.opponent());
+            let captured_disks = movegen::flips(self.disks(p), opp_disks, i);
             if p == Player::White {
                 return Board(opp_disks ^ captured_disks, my_disks ^ captured_disks);
             }
-            return Board(my_disks ^ captured_disks, opp_disks ^ captured_disks);
+            Board(my_disks ^ captured_disks, opp_disks ^ captured_disks)
     }
     pub fn valid_moves(&self, p: Player) -> impl Iterator<Item = Action> + '_{
-        let empty = !(self.0 | self.1);
-        let mut moves = 0u64;
-        let my_disks = self.get_disks(p);
-        let opp_disks = self.get_disks(p.opponent());
-        for d in CompassRose::iter() {
-            let x = my_disks.shift(d) & opp_disks;
-            let x = x | (x.shift(d) & opp_disks);
-            let x = x | (x.shift(d) & opp_disks);
-            let x = x | (x.shift(d) & opp_disks);
-            let x = x | (x.shift(d) & opp_disks);
-            let x = x | (x.shift(d) & opp_disks);
-            moves |= x.shift(d) & empty;
-        }
+        let my_disks = self.disks(p);
+        let opp_disks = self.disks(p.opponent());
         let f = match p {
             Player::Black=> |b: Board| b,
             Player::White=> |b: Board| Board(b.1, b.0),
         };
-        let res = std::iter::from_fn(move|| {
-            if moves == 0 {
-                return None;
-            }
-            let i = moves & (!moves + 1);
-            moves ^= i;
-            let my_disks = my_disks | i;
-            let mut captured_disks = 0u64;
-            for d in CompassRose::iter() {
-                let x = i.shift(d) & opp_disks;
-                let x = x | (x.shift(d) & opp_disks);
-                let x = x | (x.shift(d) & opp_disks);
-                let x = x | (x.shift(d) & opp_disks);
-                let x = x | (x.shift(d) & opp_disks);
-                let x = x | (x.shift(d) & opp_disks);
-                if x.shift(d) & my_disks != 0 {
-                    captured_disks |= x; 
-                }
-            }
-            let pos = unsafe {
-                std::mem::transmute(i.trailing_zeros() as u8)
-            };
-            return Some (Action{ at: pos, board: f(Board(my_disks ^ captured_disks, opp_disks ^ captured_disks))});
-        });
-        // let res = Pos::iter().filter_map(move|i| {
-        //     if moves & (MASK << *i as u64) != 0 {
-        //         let my_disks = my_disks | (MASK << *i as u64);
-        //         let mut captured_disks = 0u64;
-        //         for d in CompassRose::iter() {
-        //             let x = (MASK << *i as u64).shift(d) & opp_disks;
-        //             let x = x | (x.shift(d) & opp_disks);
-        //             let x = x | (x.shift(d) & opp_disks);
-        //             let x = x | (x.shift(d) & opp_disks);
-        //             let x = x | (x.shift(d) & opp_disks);
-        //             let x = x | (x.shift(d) & opp_disks);
-        //             if x.shift(d) & my_disks != 0 {
-        //                 captured_disks |= x; 
-        //             }
-        //         }
-        //         return Some (Action{ at: *i, board: f(Board(my_disks ^ captured_disks, opp_disks ^ captured_disks))});
-        //     }
-        //     return None
-        // });
-        res
+        self.moves(p).map(move |pos| {
+            let captured_disks = movegen::flips(my_disks, opp_disks, pos);
+            let my_disks = my_disks | pos.mask();
+            Action{ at: pos, board: f(Board(my_disks ^ captured_disks, opp_disks ^ captured_disks))}
+        })
     }    
-    pub fn valid_moves_fast(&self, p: Player) -> u64 {
-        let empty = !(self.0 | self.1);
-        let mut moves = 0u64;
-        let my_disks = self.get_disks(p);
-        let opp_disks = self.get_disks(p.opponent());
-        for d in CompassRose::iter() {
-            let x = my_disks.shift(d) & opp_disks;
-            let x = x | (x.shift(d) & opp_disks);
-            let x = x | (x.shift(d) & opp_disks);
-            let x = x | (x.shift(d) & opp_disks);
-            let x = x | (x.shift(d) & opp_disks);
-            let x = x | (x.shift(d) & opp_disks);
-            moves |= x.shift(d) & empty;
+    pub fn valid_moves_fast(&self, p: Player) -> SquareSet {
+        SquareSet::new(movegen::moves(self.disks(p), self.disks(p.opponent())))
+    }
+    /// Legal moves of `p`, without computing the resulting boards.
+    pub fn moves(&self, p: Player) -> MoveList {
+        MoveList(self.valid_moves_fast(p))
+    }
+    /// Disks of the opponent that `p` would flip by playing at `pos`.
+    ///
+    /// Empty if the move is not legal.
+    pub fn flips(&self, p: Player, pos: Pos) -> SquareSet {
+        SquareSet::new(movegen::flips(self.disks(p), self.disks(p.opponent()), pos))
+    }
+    /// Plays `pos` for `p` in place, returning what [`Board::unmake_move`] needs to take it back.
+    ///
+    /// Returns `None` and leaves the board untouched if the move is not legal.
+    pub fn make_move(&mut self, p: Player, pos: Pos) -> Option<Move> {
+        let flips = self.flips(p, pos);
+        if flips.is_empty() {
+            return None;
         }
-        moves
+        let mv = Move { at: pos, flips };
+        self.apply(p, mv);
+        Some(mv)
+    }
+    /// Takes back `mv`, which must be the last move made by `p` on this board.
+    pub fn unmake_move(&mut self, p: Player, mv: Move) {
+        self.apply(p, mv);
+    }
+    fn apply(&mut self, p: Player, mv: Move) {
+        let flips = mv.flips.bits();
+        let (mine, theirs) = match p {
+            Player::Black => (&mut self.0, &mut self.1),
+            Player::White => (&mut self.1, &mut self.0),
+        };
+        *mine ^= flips | mv.at.mask();
+        *theirs ^= flips;
     }
 }
 
+/// A move together with the disks it flipped, as returned by [`Board::make_move`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Move {
+    pub at: Pos,
+    pub flips: SquareSet,
+}
+
+/// Legal moves of a position as bare squares, created by [`Board::moves`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct MoveList(SquareSet);
+
+impl MoveList {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    pub fn contains(&self, pos: Pos) -> bool {
+        self.0.contains(pos)
+    }
+    pub fn squares(&self) -> SquareSet {
+        self.0
+    }
+}
+impl Iterator for MoveList {
+    type Item = Pos;
+    fn next(&mut self) -> Option<Pos> {
+        let pos = self.0.first()?;
+        self.0.remove(pos);
+        Some(pos)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len(), Some(self.len()))
+    }
+}
+impl ExactSizeIterator for MoveList {}
+impl std::iter::FusedIterator for MoveList {}
+
 // (Player, Board, Result<Pos, bool>) means (whose turn, board, last move (Err(true) means passed, Err(false) means initial))))))
 pub fn boards_from<'a>(s0: Board, mut positions: impl Iterator<Item = Pos> + 'a) -> impl Iterator<Item = (Player, Board, Result<Pos, bool>)> + 'a {
-    return std::iter::successors(Some((Player::Black, s0, Err(false))), move |&(p, b, last)| {
+    std::iter::successors(Some((Player::Black, s0, Err(false))), move |&(p, b, last)| {
         let o = p.opponent();
-        match b.valid_moves_fast(p) {
-            0 => {
-                if last == Err(true) { // both players passed
-                    assert_eq!(None, positions.next()); // no more positions
-                    return None;
-                }
-                return Some((o, b, Err(true)));
-            },
-            moves => {
-                let Some(pos) = positions.next() else {
-                    return None;
-                };
-                assert_ne!(0, moves & (MASK << pos as u64), "No valid move at {:?}, when {:?}'turn, board=\n{:?}", pos, p, b); 
-                let res = Some((o, b.place_at_unchecked(p, pos), Ok(pos)));
-                return res;
+        let moves = b.valid_moves_fast(p);
+        if moves.is_empty() {
+            if last == Err(true) { // both players passed
+                assert_eq!(None, positions.next()); // no more positions
+                return None;
             }
+            Some((o, b, Err(true)))
+        } else {
+            let pos = positions.next()?;
+            assert!(moves.contains(pos), "No valid move at {:?}, when {:?}'turn, board=\n{:?}", pos, p, b); 
+            Some((o, b.place_at_unchecked(p, pos), Ok(pos)))
         }
-    });
+    })
 }
 
 impl std::fmt::Debug for Board {
@@ -552,8 +715,78 @@ impl std::fmt::Debug for Board {
                     _ => write!(f, "?")?,
                 };
             }
-            write!(f, "\n")?;
+            writeln!(f)?;
         }
         Ok(())
     }
 }
+
+/// A board with the player to move, written as its 64 squares in reading order (A1, B1, ...,
+/// H8), `X` for Black, `O` for White and `-` for empty, then `X` or `O` for the player to
+/// move. Whitespace is ignored and `.` is also read as empty, so boards can be written as
+/// 8 lines; the initial position is
+/// `---------------------------OX------XO--------------------------- X`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Position {
+    pub board: Board,
+    pub to_move: Player,
+}
+
+#[derive(thiserror::Error, Clone, PartialEq, Eq, Debug)]
+pub enum ParsePositionError {
+    #[error("expected 64 squares and the player to move, found {0} characters")]
+    Length(usize),
+    #[error("unexpected {0:?}, expected X, O, - or .")]
+    Square(char),
+    #[error("unexpected {0:?} for the player to move, expected X or O")]
+    ToMove(char),
+}
+
+impl std::str::FromStr for Position {
+    type Err = ParsePositionError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let chars: Vec<char> = s.chars().filter(|c| !c.is_whitespace()).collect();
+        let [squares @ .., to_move] = &chars[..] else {
+            return Err(ParsePositionError::Length(0));
+        };
+        if squares.len() != 64 {
+            return Err(ParsePositionError::Length(chars.len()));
+        }
+        let (mut black, mut white) = (SquareSet::EMPTY, SquareSet::EMPTY);
+        for (&pos, &c) in Pos::iter().zip(squares) {
+            match c.to_ascii_uppercase() {
+                'X' | '*' => black.insert(pos),
+                'O' => white.insert(pos),
+                '-' | '.' => {}
+                _ => return Err(ParsePositionError::Square(c)),
+            }
+        }
+        let to_move = match to_move.to_ascii_uppercase() {
+            'X' | '*' => Player::Black,
+            'O' => Player::White,
+            _ => return Err(ParsePositionError::ToMove(*to_move)),
+        };
+        Ok(Position {
+            board: Board::from_disks(black, white),
+            to_move,
+        })
+    }
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for pos in Pos::iter() {
+            let c = match self.board.get(pos) {
+                Some(Player::Black) => 'X',
+                Some(Player::White) => 'O',
+                None => '-',
+            };
+            write!(f, "{}", c)?;
+        }
+        let to_move = match self.to_move {
+            Player::Black => 'X',
+            Player::White => 'O',
+        };
+        write!(f, " {}", to_move)
+    }
+}
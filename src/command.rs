@@ -1,22 +1,32 @@
-use crate::agent::Agent;
+use crate::agent::{Agent, GameEnd};
 use crate::board::{Action, Board, Player, Pos};
-use std::io::Write;
+use std::io::{BufRead, BufReader, BufWriter, Write};
 use std::net::TcpStream;
+use std::ops::ControlFlow;
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct Milliseconds(u32);
+pub struct Milliseconds(pub u32);
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct Score<T>(T);
+pub struct Score<T>(pub T);
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Stat {
-    name: String,
-    score: Score<i32>,
-    n_win: u32,
-    n_lose: u32,
+    pub name: String,
+    pub score: Score<i32>,
+    pub n_win: u32,
+    pub n_lose: u32,
 }
+/// A line of the protocol, in either direction.
+///
+/// `Display` writes the line that `TryFrom<&str>` parses back to the same `Cmd`, provided
+/// names and reasons are single non-empty tokens without whitespace.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Cmd {
+    /// Sent by a client to join with its name.
+    Open {
+        name: String,
+    },
     Start {
         bw: Player,
         opponent_name: String,
@@ -40,21 +50,31 @@ impl std::fmt::Display for Cmd {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use Cmd::*;
         match self {
+            Open { name } => write!(f, "OPEN {}", name),
             Start {
                 bw,
                 opponent_name,
                 remaining_time,
-            } => write!(f, "START {:?} {} {}", bw, opponent_name, remaining_time.0),
+            } => {
+                let bw = match bw {
+                    Player::Black => "BLACK",
+                    Player::White => "WHITE",
+                };
+                write!(f, "START {} {} {}", bw, opponent_name, remaining_time.0)
+            }
             End {
                 result,
                 my_score,
                 opponent_score,
                 reason,
-            } => write!(
-                f,
-                "END {:?} {} {} {}",
-                result, my_score.0, opponent_score.0, reason
-            ),
+            } => {
+                let result = match result {
+                    Wl::Win => "WIN",
+                    Wl::Lose => "LOSE",
+                    Wl::Tie => "TIE",
+                };
+                write!(f, "END {} {} {} {}", result, my_score.0, opponent_score.0, reason)
+            }
             Move(None) => write!(f, "MOVE PASS"),
             Move(Some(pos)) => write!(f, "MOVE {}", pos),
             Ack { remaining_time } => write!(f, "ACK {}", remaining_time.0),
@@ -69,7 +89,7 @@ impl std::fmt::Display for Cmd {
     }
 }
 impl Cmd {
-    fn start<S>(bw: Player, opponent_name: S, remaining_time: Milliseconds) -> Cmd
+    pub fn start<S>(bw: Player, opponent_name: S, remaining_time: Milliseconds) -> Cmd
     where
         S: Into<String>,
     {
@@ -80,7 +100,7 @@ impl Cmd {
             remaining_time,
         }
     }
-    fn end<S>(result: Wl, my_score: u32, opponent_score: u32, reason: S) -> Cmd
+    pub fn end<S>(result: Wl, my_score: u32, opponent_score: u32, reason: S) -> Cmd
     where
         S: Into<String>,
     {
@@ -91,7 +111,7 @@ impl Cmd {
             reason: reason.into(),
         }
     }
-    fn ack(remaining_time: Milliseconds) -> Cmd {
+    pub fn ack(remaining_time: Milliseconds) -> Cmd {
         Cmd::Ack { remaining_time }
     }
 }
@@ -128,6 +148,9 @@ impl TryFrom<&str> for Cmd {
         let tokens: Vec<_> = s.split_whitespace().collect();
         match &tokens[..] {
             [] => Err(CmdParseError::Missing("command", "command")),
+            ["OPEN"] => Err(CmdParseError::Missing("OPEN", "player name")),
+            ["OPEN", name] => Ok(Cmd::Open { name: name.to_string() }),
+            ["OPEN", _, s, ..] => Err(CmdParseError::UnexpectedToken("OPEN", s.to_string())),
             ["START"] => Err(CmdParseError::Missing("START", "WHITE or BLACK")),
             ["START", _] => Err(CmdParseError::Missing("START", "opponent name")),
             ["START", _, _] => Err(CmdParseError::Missing("START", "remaining time")),
@@ -170,7 +193,7 @@ impl TryFrom<&str> for Cmd {
                         // assert_eq!(n, m);
                         Ok(Cmd::end(Wl::Tie, n, m, reason.to_string()))
                     }
-                    _ => unreachable!(),
+                    _ => Err(CmdParseError::ExpectedFound("END", "WIN, LOSE or TIE", wl.to_string())),
                 }
             }
             ["END", _, _, _, _, s, ..] => Err(CmdParseError::UnexpectedToken("END", s.to_string())),
@@ -257,6 +280,280 @@ impl TryFrom<String> for Cmd {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
+
+    /// Names and reasons: any non-empty run of non-whitespace characters.
+    fn token() -> impl Strategy<Value = String> {
+        "[^\\s]{1,12}"
+    }
+
+    fn cmd() -> impl Strategy<Value = Cmd> {
+        let player = prop_oneof![Just(Player::Black), Just(Player::White)];
+        let wl = prop_oneof![Just(Wl::Win), Just(Wl::Lose), Just(Wl::Tie)];
+        let pos = (0..64u8).prop_map(|i| Pos::from_index(i).unwrap());
+        let stat = (token(), any::<i32>(), any::<u32>(), any::<u32>()).prop_map(|(name, score, n_win, n_lose)| Stat {
+            name,
+            score: Score(score),
+            n_win,
+            n_lose,
+        });
+        prop_oneof![
+            token().prop_map(|name| Cmd::Open { name }),
+            (player, token(), any::<u32>()).prop_map(|(bw, name, t)| Cmd::start(bw, name, Milliseconds(t))),
+            (wl, any::<u32>(), any::<u32>(), token()).prop_map(|(wl, n, m, reason)| Cmd::end(wl, n, m, reason)),
+            proptest::option::of(pos).prop_map(Cmd::Move),
+            any::<u32>().prop_map(|t| Cmd::ack(Milliseconds(t))),
+            proptest::collection::vec(stat, 0..4).prop_map(|stat| Cmd::Bye { stat }),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_round_trip(cmd in cmd()) {
+            let line = cmd.to_string();
+            prop_assert_eq!(Cmd::try_from(line.as_str()).unwrap(), cmd);
+            // lines read from a socket keep their line break
+            prop_assert_eq!(Cmd::try_from(format!("{}\r\n", line)).unwrap().to_string(), line);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_parse_never_panics(line in "\\PC*", end in "END [A-Z]{0,5} [0-9]{1,3} [0-9]{1,3} [A-Z_]{1,12}") {
+            let _ = Cmd::try_from(line.as_str());
+            let _ = Cmd::try_from(end.as_str());
+        }
+    }
+
+    #[test]
+    fn test_try_from_bad_end() {
+        assert!(matches!(
+            Cmd::try_from("END DRAW 32 32 DOUBLE_PASS"),
+            Err(CmdParseError::ExpectedFound("END", "WIN, LOSE or TIE", _))
+        ));
+    }
+
+    enum Step {
+        Send(&'static str),
+        Expect(&'static str),
+    }
+
+    /// Runs a session with `recovery` against a server following `script`, checking that the
+    /// session writes nothing more than expected.
+    fn run_script(recovery: Recovery, script: Vec<Step>) -> Result<(), SessionError> {
+        run_script_with(recovery, script, &mut ())
+    }
+
+    fn run_script_with(recovery: Recovery, script: Vec<Step>, handler: &mut impl SessionHandler) -> Result<(), SessionError> {
+        use std::io::{BufRead, Read};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+            let mut writer = &stream;
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            assert_eq!(line, "OPEN me\n");
+            for step in script {
+                match step {
+                    Step::Send(s) => writeln!(writer, "{}", s).unwrap(),
+                    Step::Expect(prefix) => {
+                        line.clear();
+                        reader.read_line(&mut line).unwrap();
+                        assert!(line.starts_with(prefix), "expected {:?}, got {:?}", prefix, line);
+                    }
+                }
+            }
+            let mut rest = String::new();
+            reader.read_to_string(&mut rest).unwrap();
+            assert_eq!(rest, "");
+        });
+        let stream = TcpStream::connect(addr).unwrap();
+        let result = Session::new("me")
+            .recovery(recovery)
+            .launch_with(&mut crate::agent::RandomAgent, &stream, handler);
+        drop(stream);
+        server.join().unwrap();
+        result
+    }
+
+    #[test]
+    fn test_recovery_abort() {
+        use Step::*;
+        let result = run_script(Recovery::Abort, vec![Send("START WHITE bob 1000"), Send("MOVE A1")]);
+        assert!(matches!(result, Err(SessionError::IllegalOpponentMove { pos: Pos::A1, .. })));
+        let result = run_script(Recovery::Abort, vec![Send("HELLO")]);
+        assert!(matches!(result, Err(SessionError::CmdParseError(CmdParseError::UnknownCommand(_)))));
+        let result = run_script(Recovery::Abort, vec![Send("MOVE D3")]);
+        assert!(matches!(result, Err(SessionError::UnexpectedCmd(Cmd::Move(_)))));
+    }
+
+    #[test]
+    fn test_recovery_resync() {
+        use Step::*;
+        let result = run_script(Recovery::Resync, vec![
+            Send("START WHITE bob 1000"),
+            Send("MOVE A1"),
+            Expect("MOVE "),
+            Send("HELLO"),
+            Send("END WIN 4 1 DOUBLE_PASS"),
+            Send("BYE"),
+        ]);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_recovery_abandon_game() {
+        use Step::*;
+        let result = run_script(Recovery::AbandonGame, vec![
+            Send("START WHITE bob 1000"),
+            Send("MOVE A1"),
+            Send("MOVE B1"),
+            Send("END LOSE 2 3 TIMEOUT"),
+            Send("START BLACK bob 1000"),
+            Expect("MOVE "),
+            Send("BYE bob 1 1 0 me -1 0 1"),
+        ]);
+        assert!(result.is_ok());
+    }
+
+    /// Logs every event it receives.
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+    impl SessionHandler for Recorder {
+        fn on_open(&mut self, name: &str) {
+            self.0.push(format!("open {}", name));
+        }
+        fn on_start(&mut self, bw: Player, opponent_name: &str, remaining_time: Milliseconds) {
+            self.0.push(format!("start {:?} {} {}", bw, opponent_name, remaining_time.0));
+        }
+        fn on_move(&mut self, p: Player, pos: Option<Pos>, board: &Board) {
+            self.0.push(format!("move {:?} {:?} {}", p, pos, board.count(Player::Black) + board.count(Player::White)));
+        }
+        fn on_ack(&mut self, remaining_time: Milliseconds) {
+            self.0.push(format!("ack {}", remaining_time.0));
+        }
+        fn on_end(&mut self, end: &GameEnd) {
+            self.0.push(format!("end {:?} {} {} {}", end.result, end.my_score, end.opponent_score, end.reason));
+        }
+        fn on_bye(&mut self, stat: &[Stat]) {
+            self.0.push(format!("bye {}", stat.len()));
+        }
+        fn on_reconnect(&mut self, attempt: u32, _error: &std::io::Error) {
+            self.0.push(format!("reconnect {}", attempt));
+        }
+    }
+
+    #[test]
+    fn test_handler() {
+        use Step::*;
+        let (mut first, mut second) = (Recorder::default(), Recorder::default());
+        let result = run_script_with(
+            Recovery::Abort,
+            vec![
+                Send("START WHITE bob 1000"),
+                Send("MOVE F5"),
+                Expect("MOVE "),
+                Send("ACK 900"),
+                Send("END LOSE 3 61 TIMEOUT"),
+                Send("BYE bob 58 1 0 me -58 0 1"),
+            ],
+            &mut (&mut first, &mut second),
+        );
+        assert!(result.is_ok());
+        let events = &first.0;
+        assert_eq!(events.len(), 7);
+        assert_eq!(events[..3], ["open me", "start White bob 1000", "move Black Some(F5) 5"]);
+        assert!(events[3].starts_with("move White Some("), "{}", events[3]);
+        assert!(events[3].ends_with(" 6"), "{}", events[3]);
+        assert_eq!(events[4..], ["ack 900", "end Lose 3 61 TIMEOUT", "bye 2"]);
+        assert_eq!(first.0, second.0);
+    }
+
+    #[test]
+    fn test_reconnect_delay() {
+        let policy = Reconnect::new(5)
+            .initial_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_secs(1));
+        let delays: Vec<_> = (1..=5).map(|i| policy.delay(i).as_millis()).collect();
+        assert_eq!(delays, [100, 200, 400, 800, 1000]);
+        assert_eq!(policy.delay(100), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_reconnect_gives_up() {
+        let policy = Reconnect::new(3).initial_delay(Duration::ZERO);
+        let mut n_connects = 0;
+        let mut recorder = Recorder::default();
+        let result = Session::new("me").launch_reconnecting(
+            &mut crate::agent::RandomAgent,
+            || {
+                n_connects += 1;
+                Err(std::io::ErrorKind::ConnectionRefused.into())
+            },
+            &policy,
+            &mut recorder,
+        );
+        assert!(matches!(result, Err(SessionError::IoError(e)) if e.kind() == std::io::ErrorKind::ConnectionRefused));
+        assert_eq!(n_connects, 4);
+        assert_eq!(recorder.0, ["reconnect 1", "reconnect 2", "reconnect 3"]);
+    }
+
+    #[test]
+    fn test_reconnect_resumes() {
+        use std::io::BufRead;
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let mut line = String::new();
+            // the first connection drops in the middle of a game
+            let (stream, _) = listener.accept().unwrap();
+            std::io::BufReader::new(&stream).read_line(&mut line).unwrap();
+            assert_eq!(line, "OPEN me\n");
+            writeln!(&stream, "START WHITE bob 1000").unwrap();
+            drop(stream);
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = std::io::BufReader::new(&stream);
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            assert_eq!(line, "OPEN me\n");
+            writeln!(&stream, "START BLACK bob 1000").unwrap();
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            assert!(line.starts_with("MOVE "));
+            writeln!(&stream, "END WIN 1 0 TIMEOUT\nBYE").unwrap();
+        });
+        let policy = Reconnect::new(1).initial_delay(Duration::ZERO);
+        let mut recorder = Recorder::default();
+        let result = Session::new("me").recovery(Recovery::Abort).launch_reconnecting(
+            &mut crate::agent::RandomAgent,
+            || TcpStream::connect(addr),
+            &policy,
+            &mut recorder,
+        );
+        server.join().unwrap();
+        assert!(result.is_ok(), "{:?}", result);
+        let events: Vec<_> = recorder.0.iter().filter(|e| !e.starts_with("move")).collect();
+        assert_eq!(events, [
+            "open me",
+            "start White bob 1000",
+            "reconnect 1",
+            "open me",
+            "start Black bob 1000",
+            "end Win 1 0 TIMEOUT",
+            "bye 0",
+        ]);
+    }
+
+    #[test]
+    fn test_canonical_form() -> Result<(), <Cmd as TryFrom<&'static str>>::Error> {
+        assert_eq!(Cmd::try_from("  MOVE   d3 \n")?.to_string(), "MOVE D3");
+        assert_eq!(Cmd::try_from("OPEN Joe")?, Cmd::Open { name: "Joe".to_string() });
+        assert!(matches!(Cmd::try_from("OPEN"), Err(CmdParseError::Missing("OPEN", _))));
+        assert!(matches!(Cmd::try_from("OPEN Joe Smith"), Err(CmdParseError::UnexpectedToken("OPEN", _))));
+        Ok(())
+    }
     #[test]
     fn test_try_from_start() -> Result<(), <Cmd as TryFrom<&'static str>>::Error> {
         for (bw, bw1) in std::iter::zip(["WHITE", "BLACK"], [Player::White, Player::Black]) {
@@ -302,6 +599,19 @@ mod tests {
         Ok(())
     }
     #[test]
+    fn test_display_start_end() -> Result<(), <Cmd as TryFrom<&'static str>>::Error> {
+        for cmd in [
+            Cmd::start(Player::Black, "Joe", Milliseconds(60000)),
+            Cmd::start(Player::White, "Adam", Milliseconds(0)),
+            Cmd::end(Wl::Win, 40, 24, "DOUBLE_PASS"),
+            Cmd::end(Wl::Tie, 32, 32, "DOUBLE_PASS"),
+        ] {
+            assert_eq!(Cmd::try_from(cmd.to_string())?, cmd);
+        }
+        assert_eq!(Cmd::end(Wl::Lose, 0, 4, "TIMEOUT").to_string(), "END LOSE 0 4 TIMEOUT");
+        Ok(())
+    }
+    #[test]
     fn test_try_from_move() -> Result<(), <Cmd as TryFrom<&'static str>>::Error> {
         for rank in ['1', '2', '3', '4', '5', '6', '7', '8'] {
             for file in ['A', 'B', 'C', 'D', 'E', 'F', 'G', 'H'] {
@@ -365,158 +675,484 @@ mod tests {
 
 #[derive(thiserror::Error, Debug)]
 pub enum SessionError {
-    #[error("IO error:")]
+    #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
     #[error("Cmd parse error: {0}")]
     CmdParseError(#[from] CmdParseError),
     #[error("Unexpected command: {0:?}")]
     UnexpectedCmd(Cmd),
+    #[error("Opponent move {pos} is not legal")]
+    IllegalOpponentMove { pos: Pos, board: Board },
     #[error("Missing event handlers for {0:?}")]
     MissingEventHandlersFor(Cmd),
 }
 
+/// What a [`Session`] does when the server sends something it cannot make sense of: a line
+/// that does not parse, a command out of place, or an opponent move that is not legal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Recovery {
+    /// Stop and return the error from [`Session::launch`].
+    Abort,
+    /// Log the error and carry on: bad lines and commands out of place are skipped, and an
+    /// illegal opponent move is applied as given, so that the board matches the server's.
+    #[default]
+    Resync,
+    /// Log the error and sit out the rest of the game, waiting for its `END`.
+    AbandonGame,
+}
+
+/// State of the game a [`Session`] is playing.
+pub(crate) struct Game {
+    pub(crate) bw: Player,
+    pub(crate) board: Board,
+    pub(crate) abandoned: bool,
+}
+
+/// Observer of the events of a [`Session`], to log clocks, record games, keep statistics or
+/// drive a UI. Every method does nothing by default.
+pub trait SessionHandler {
+    /// We joined the server as `name`.
+    fn on_open(&mut self, _name: &str) {}
+    /// A game starts with us playing `bw`.
+    fn on_start(&mut self, _bw: Player, _opponent_name: &str, _remaining_time: Milliseconds) {}
+    /// `p` played `pos`, `None` for a pass, leading to `board`. Called for both sides.
+    fn on_move(&mut self, _p: Player, _pos: Option<Pos>, _board: &Board) {}
+    /// The server acknowledged our move, with the time we have left.
+    fn on_ack(&mut self, _remaining_time: Milliseconds) {}
+    fn on_end(&mut self, _end: &GameEnd) {}
+    fn on_bye(&mut self, _stat: &[Stat]) {}
+    /// The connection failed with `error`; reconnection `attempt`, counting from 1, follows.
+    fn on_reconnect(&mut self, _attempt: u32, _error: &std::io::Error) {}
+}
+
+impl SessionHandler for () {}
+
+impl<H: SessionHandler + ?Sized> SessionHandler for &mut H {
+    fn on_open(&mut self, name: &str) {
+        (**self).on_open(name)
+    }
+    fn on_start(&mut self, bw: Player, opponent_name: &str, remaining_time: Milliseconds) {
+        (**self).on_start(bw, opponent_name, remaining_time)
+    }
+    fn on_move(&mut self, p: Player, pos: Option<Pos>, board: &Board) {
+        (**self).on_move(p, pos, board)
+    }
+    fn on_ack(&mut self, remaining_time: Milliseconds) {
+        (**self).on_ack(remaining_time)
+    }
+    fn on_end(&mut self, end: &GameEnd) {
+        (**self).on_end(end)
+    }
+    fn on_bye(&mut self, stat: &[Stat]) {
+        (**self).on_bye(stat)
+    }
+    fn on_reconnect(&mut self, attempt: u32, error: &std::io::Error) {
+        (**self).on_reconnect(attempt, error)
+    }
+}
+
+/// Both handlers, in order.
+impl<A: SessionHandler, B: SessionHandler> SessionHandler for (A, B) {
+    fn on_open(&mut self, name: &str) {
+        self.0.on_open(name);
+        self.1.on_open(name);
+    }
+    fn on_start(&mut self, bw: Player, opponent_name: &str, remaining_time: Milliseconds) {
+        self.0.on_start(bw, opponent_name, remaining_time);
+        self.1.on_start(bw, opponent_name, remaining_time);
+    }
+    fn on_move(&mut self, p: Player, pos: Option<Pos>, board: &Board) {
+        self.0.on_move(p, pos, board);
+        self.1.on_move(p, pos, board);
+    }
+    fn on_ack(&mut self, remaining_time: Milliseconds) {
+        self.0.on_ack(remaining_time);
+        self.1.on_ack(remaining_time);
+    }
+    fn on_end(&mut self, end: &GameEnd) {
+        self.0.on_end(end);
+        self.1.on_end(end);
+    }
+    fn on_bye(&mut self, stat: &[Stat]) {
+        self.0.on_bye(stat);
+        self.1.on_bye(stat);
+    }
+    fn on_reconnect(&mut self, attempt: u32, error: &std::io::Error) {
+        self.0.on_reconnect(attempt, error);
+        self.1.on_reconnect(attempt, error);
+    }
+}
+
+/// How [`Session::launch_reconnecting`] gets back to the server after losing the connection:
+/// up to `max_attempts` times in a row, waiting `initial_delay` before the first attempt and
+/// twice as long before each next one, up to `max_delay`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reconnect {
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Reconnect {
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+
+    pub fn initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
+
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Time to wait before reconnection `attempt`, counting from 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt.saturating_sub(1)).unwrap_or(u32::MAX);
+        self.initial_delay.saturating_mul(factor).min(self.max_delay)
+    }
+}
+
+/// What the agent of a session has to do after [`Session::handle`].
+// only the async session keeps track of the clock
+#[cfg_attr(not(feature = "async"), allow(dead_code))]
+pub(crate) enum Event {
+    /// A game started with `remaining_time` on our clock, `restart` if it cut one short; the
+    /// agent moves first with Black.
+    Start { restart: bool, remaining_time: Milliseconds },
+    /// The opponent moved, and it is the agent's turn.
+    OpponentMove(Option<Action>),
+    Ack(Milliseconds),
+    End(GameEnd),
+    Bye,
+    /// Nothing, the command having been dealt with or skipped.
+    Skip,
+}
+
+/// Notes whether a game started, which shows that a connection worked.
+pub(crate) struct Started(pub(crate) bool);
+
+impl SessionHandler for Started {
+    fn on_start(&mut self, _bw: Player, _opponent_name: &str, _remaining_time: Milliseconds) {
+        self.0 = true;
+    }
+}
+
 pub struct Session {
     pub self_name: String,
     pub opponent_name: Option<String>,
-    // pub on_open: Box<dyn FnOnce()>,
-    // pub on_black_start: Box<dyn FnOnce() -> Action>,
-    // pub on_white_start: Box<dyn FnOnce()>,
-    // pub on_move: Box<dyn Fn(Pos) -> Option<Action>>,
-    // pub on_ack: Box<dyn Fn(Milliseconds)>,
-    // pub on_end: Option<Box<dyn FnOnce(Wl, Score, Score, String) + Sync>>,
-    // pub on_bye: Option<Box<dyn FnOnce(Vec<Stat>) + Sync>>,
+    pub recovery: Recovery,
 }
 
 impl Session {
-    pub fn new(
-        self_name: impl Into<String>,
-        // on_open: impl FnOnce() + 'static,
-        // on_black_start: impl FnOnce() -> Pos + 'static,
-        // on_move: impl Fn(Pos) -> Option<Pos> + 'static,
-        // on_ack: impl Fn(Milliseconds)+ 'static) -> Self {
-    ) -> Self {
+    pub fn new(self_name: impl Into<String>) -> Self {
         Self {
             self_name: self_name.into(),
             opponent_name: None,
-            // on_open: Box::new(on_open),
-            // on_black_start: Box::new(on_black_start),
-            // on_move: Box::new(on_move),
-            // on_ack: Box::new(on_ack),
-            // on_end: None,
-            // on_bye: None,
+            recovery: Recovery::default(),
         }
     }
 
-    // pub fn on_end<F>(mut self, f: impl FnOnce(Wl, Score, Score, String) + Sync + 'static) -> Self {
-    //     self.on_end = Some(Box::new(f));
-    //     self
-    // }
+    pub fn recovery(mut self, recovery: Recovery) -> Self {
+        self.recovery = recovery;
+        self
+    }
 
-    // pub fn on_bye<F>(mut self, f: impl FnOnce(Vec<Stat>) + Sync + 'static) -> Self {
-    //     self.on_bye = Some(Box::new(f));
-    //     self
-    // }
-    fn read_cmd(reader: &mut impl std::io::BufRead) -> Result<Cmd, SessionError> {
+    fn read_line(reader: &mut impl BufRead) -> Result<String, SessionError> {
         let mut line = String::new();
         let nbytes = reader.read_line(&mut line)?;
         
         if nbytes == 0 {
             return Err(SessionError::IoError(std::io::Error::from(std::io::ErrorKind::UnexpectedEof)));
         }
-        let cmd = Cmd::try_from(line.as_str())?;
-        Ok(cmd)
+        Ok(line)
+    }
+    fn write_cmd(&self, writer: &mut impl Write, cmd: &Cmd) -> Result<(), SessionError> {
+        writeln!(writer, "{}", cmd)?;
+        writer.flush()?;
+        eprintln!("[{}] 发 {}", self.self_name, cmd);
+        Ok(())
     }
 
+    /// Handles `err` according to the recovery policy: returns it with [`Recovery::Abort`],
+    /// logs it otherwise, abandoning the current game with [`Recovery::AbandonGame`].
+    pub(crate) fn recover(&self, err: SessionError, game: &mut Option<Game>) -> Result<(), SessionError> {
+        match self.recovery {
+            Recovery::Abort => return Err(err),
+            Recovery::Resync => eprintln!("[{}] {}, carrying on", self.self_name, err),
+            Recovery::AbandonGame => {
+                eprintln!("[{}] {}, waiting for the end of the game", self.self_name, err);
+                if let Some(game) = game {
+                    game.abandoned = true;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Plays on the server at the other end of `stream` until it says `BYE`.
     pub fn launch(&mut self, agent: &mut impl Agent, stream: &TcpStream) -> Result<(), SessionError> {
-        let mut reader = std::io::BufReader::new(stream);
-        let mut writer = std::io::BufWriter::new(stream);
+        self.launch_with(agent, stream, &mut ())
+    }
 
-        writeln!(writer, "OPEN {}", self.self_name)?;
-        eprintln!("[{}] 发 OPEN {}", self.self_name, self.self_name);
-        writer.flush()?;
-        // (self.on_open)();
-        agent.initialize();
+    /// Like [`Session::launch`], reporting every event to `handler`.
+    pub fn launch_with(
+        &mut self,
+        agent: &mut impl Agent,
+        stream: &TcpStream,
+        handler: &mut impl SessionHandler,
+    ) -> Result<(), SessionError> {
+        self.run_with(agent, BufReader::new(stream), BufWriter::new(stream), handler)
+    }
+
+    /// Like [`Session::launch_with`], but opens connections with `connect` and, whenever one
+    /// cannot be made or is lost, opens a new one according to `policy` and sends `OPEN` again
+    /// with the same name. The game in progress is lost; `handler` follows every connection,
+    /// so the statistics it keeps span all of them. The count of failed attempts starts over
+    /// once a game starts.
+    pub fn launch_reconnecting(
+        &mut self,
+        agent: &mut impl Agent,
+        mut connect: impl FnMut() -> std::io::Result<TcpStream>,
+        policy: &Reconnect,
+        handler: &mut impl SessionHandler,
+    ) -> Result<(), SessionError> {
+        let mut attempt = 0;
         loop {
-            let cmd = Session::read_cmd(&mut reader)?;
-            eprintln!("[{}] 收 {}", self.self_name, cmd);
+            let mut started = Started(false);
+            let result = match connect() {
+                Ok(stream) => self.launch_with(agent, &stream, &mut (&mut *handler, &mut started)),
+                Err(err) => Err(err.into()),
+            };
+            match self.retry(policy, &mut attempt, started.0, result, handler) {
+                ControlFlow::Continue(delay) => std::thread::sleep(delay),
+                ControlFlow::Break(result) => return result,
+            }
+        }
+    }
 
-            let mut board: Board = Board::initial();
+    /// Decides, after a connection ended with `result`, whether `policy` allows another one
+    /// and after how long. `attempt` counts the failures in a row, which `started` resets.
+    pub(crate) fn retry(
+        &self,
+        policy: &Reconnect,
+        attempt: &mut u32,
+        started: bool,
+        result: Result<(), SessionError>,
+        handler: &mut impl SessionHandler,
+    ) -> ControlFlow<Result<(), SessionError>, Duration> {
+        if started {
+            *attempt = 0;
+        }
+        match result {
+            Err(SessionError::IoError(err)) if *attempt < policy.max_attempts => {
+                *attempt += 1;
+                let delay = policy.delay(*attempt);
+                eprintln!(
+                    "[{}] {}, reconnecting in {:.1}s ({}/{})",
+                    self.self_name,
+                    err,
+                    delay.as_secs_f64(),
+                    attempt,
+                    policy.max_attempts
+                );
+                handler.on_reconnect(*attempt, &err);
+                ControlFlow::Continue(delay)
+            }
+            result => ControlFlow::Break(result),
+        }
+    }
 
-            match cmd {
-                Cmd::Start {
-                    bw,
-                    opponent_name,
-                    remaining_time,
-                } => {
-                    self.opponent_name = Some(opponent_name);
-                    if bw == Player::Black {
-                        
-                        let action = agent.select_move(&board, bw).expect("no move");
-                        board = action.board;
-                        writeln!(writer, "MOVE {}", action.at)?;
-                        writer.flush()?;
-                        eprintln!("[{}] 发 MOVE {}", self.self_name, action.at);
-                    } 
-
-                    loop {
-                        let cmd = Session::read_cmd(&mut reader)?;
-                        eprintln!("[{}] 收 {}", self.self_name, cmd);
-                        match cmd {
-                            Cmd::Start { .. } | Cmd::Bye { .. } => {
-                                return Err(SessionError::UnexpectedCmd(cmd));
-                            }
-                            Cmd::Move(pos) => {
-                                if let Some(opponent_action) = pos.map(|pos| {
-                                    board
-                                        .valid_moves(bw.opponent())
-                                        .find_map(
-                                            |a| if a.at == pos { Some(a) } else { None },
-                                        )
-                                        .unwrap()
-                                }) {
-                                    board = opponent_action.board;
-                                    agent.opponent_move_callback(Some(opponent_action));
-                                } else {
-                                    agent.opponent_move_callback(None);
-                                }
-
-                                if let Some(pos) = agent.select_move(&board, bw).map(|action| {
-                                    board = action.board;
-                                    action.at
-                                }) {
-                                    writeln!(writer, "MOVE {}", pos)?;
-                                    writer.flush()?;
-                                    eprintln!("[{}] 发 MOVE {}", self.self_name, pos);
-                                } else {
-                                    writeln!(writer, "MOVE PASS")?;
-                                    writer.flush()?;
-                                    eprintln!("[{}] 发 MOVE PASS", self.self_name);
-                                }
-                            }
-                            Cmd::Ack { remaining_time } => {
-                                // (self.on_ack)(remaining_time);
-                            }
-                            Cmd::End {
-                                result,
-                                my_score,
-                                opponent_score,
-                                reason,
-                            } => {
-                                agent.initialize();
-                                // self.on_end
-                                //     .map(|f| f(result, my_score, opponent_score, reason));
-                                break;
-                            }
-                        }
+    /// Plays on a server reached through any transport: commands are read from `reader` and
+    /// written to `writer`, which is flushed after every line.
+    pub fn run(&mut self, agent: &mut impl Agent, reader: impl BufRead, writer: impl Write) -> Result<(), SessionError> {
+        self.run_with(agent, reader, writer, &mut ())
+    }
+
+    /// Like [`Session::run`], reporting every event to `handler`.
+    pub fn run_with(
+        &mut self,
+        agent: &mut impl Agent,
+        mut reader: impl BufRead,
+        mut writer: impl Write,
+        handler: &mut impl SessionHandler,
+    ) -> Result<(), SessionError> {
+        let open = Cmd::Open {
+            name: self.self_name.clone(),
+        };
+        self.write_cmd(&mut writer, &open)?;
+        handler.on_open(&self.self_name);
+        agent.initialize();
+        let mut game: Option<Game> = None;
+        loop {
+            let line = Session::read_line(&mut reader)?;
+            match self.handle(Cmd::try_from(line.as_str()), &mut game, handler)? {
+                Event::Start { restart, .. } => {
+                    if restart {
+                        agent.initialize();
+                    }
+                    let current = game.as_mut().expect("game in progress");
+                    if current.bw == Player::Black {
+                        self.play(agent, current, &mut writer, handler)?;
                     }
                 }
-                Cmd::Bye { stat } => {
-                    return Ok(());
+                Event::OpponentMove(action) => {
+                    agent.opponent_move_callback(action);
+                    let current = game.as_mut().expect("game in progress");
+                    self.play(agent, current, &mut writer, handler)?;
+                }
+                Event::End(end) => {
+                    agent.game_end(&end);
+                    agent.initialize();
+                }
+                Event::Bye => return Ok(()),
+                Event::Ack(_) | Event::Skip => {}
+            }
+        }
+    }
+
+    /// Handles a command, or the error parsing it, for any way of running a session: keeps
+    /// `game` and the handler up to date and applies the recovery policy, leaving to the
+    /// caller what the agent has to do.
+    pub(crate) fn handle(
+        &mut self,
+        cmd: Result<Cmd, CmdParseError>,
+        game: &mut Option<Game>,
+        handler: &mut impl SessionHandler,
+    ) -> Result<Event, SessionError> {
+        let cmd = match cmd {
+            Ok(cmd) => cmd,
+            Err(err) => {
+                self.recover(err.into(), game)?;
+                return Ok(Event::Skip);
+            }
+        };
+        eprintln!("[{}] 收 {}", self.self_name, cmd);
+        match cmd {
+            Cmd::Start {
+                bw,
+                ref opponent_name,
+                remaining_time,
+            } => {
+                self.opponent_name = Some(opponent_name.clone());
+                handler.on_start(bw, opponent_name, remaining_time);
+                let restart = game.is_some();
+                if restart {
+                    self.recover(SessionError::UnexpectedCmd(cmd), game)?;
                 }
-                _ => {
-                    return Err(SessionError::UnexpectedCmd(cmd));
+                *game = Some(Game {
+                    bw,
+                    board: Board::initial(),
+                    abandoned: false,
+                });
+                Ok(Event::Start { restart, remaining_time })
+            }
+            Cmd::Move(pos) => {
+                let Some(action) = self.apply_opponent_move(pos, game)? else {
+                    return Ok(Event::Skip);
+                };
+                let current = game.as_ref().expect("game in progress");
+                handler.on_move(current.bw.opponent(), pos, &current.board);
+                Ok(Event::OpponentMove(action))
+            }
+            Cmd::Ack { remaining_time } => {
+                handler.on_ack(remaining_time);
+                Ok(Event::Ack(remaining_time))
+            }
+            Cmd::End {
+                result,
+                my_score,
+                opponent_score,
+                reason,
+            } => {
+                if game.take().is_none() {
+                    self.recover(
+                        SessionError::UnexpectedCmd(Cmd::end(result, my_score.0, opponent_score.0, reason.clone())),
+                        game,
+                    )?;
                 }
+                let end = GameEnd {
+                    result,
+                    my_score: my_score.0,
+                    opponent_score: opponent_score.0,
+                    reason,
+                };
+                handler.on_end(&end);
+                Ok(Event::End(end))
+            }
+            Cmd::Bye { stat } => {
+                handler.on_bye(&stat);
+                Ok(Event::Bye)
+            }
+            Cmd::Open { .. } => {
+                self.recover(SessionError::UnexpectedCmd(cmd), game)?;
+                Ok(Event::Skip)
             }
         }
     }
+
+    /// Applies the opponent's move `pos` to the game in progress and returns it, or `None` if
+    /// it is to be ignored. A move out of place or not legal goes through the recovery policy;
+    /// [`Recovery::Resync`] plays an illegal move anyway, the server having the last word.
+    fn apply_opponent_move(
+        &self,
+        pos: Option<Pos>,
+        game: &mut Option<Game>,
+    ) -> Result<Option<Option<Action>>, SessionError> {
+        let Some((bw, board)) = game.as_ref().filter(|g| !g.abandoned).map(|g| (g.bw, g.board)) else {
+            if game.is_none() {
+                self.recover(SessionError::UnexpectedCmd(Cmd::Move(pos)), game)?;
+            }
+            return Ok(None);
+        };
+        let o = bw.opponent();
+        let action = match pos {
+            None => None,
+            Some(pos) => match board.valid_moves(o).find(|a| a.at == pos) {
+                Some(action) => Some(action),
+                None => {
+                    self.recover(SessionError::IllegalOpponentMove { pos, board }, game)?;
+                    if self.recovery == Recovery::AbandonGame {
+                        return Ok(None);
+                    }
+                    let flips = board.flips(o, pos);
+                    let mine = board.get_disks(bw) - flips - pos;
+                    let theirs = board.get_disks(o) | flips | pos;
+                    let board = match o {
+                        Player::Black => Board::from_disks(theirs, mine),
+                        Player::White => Board::from_disks(mine, theirs),
+                    };
+                    Some(Action { at: pos, board })
+                }
+            },
+        };
+        if let (Some(current), Some(action)) = (game.as_mut(), action) {
+            current.board = action.board;
+        }
+        Ok(Some(action))
+    }
+
+    /// Asks `agent` for its move in `game` and sends it.
+    fn play(
+        &self,
+        agent: &mut impl Agent,
+        game: &mut Game,
+        writer: &mut impl Write,
+        handler: &mut impl SessionHandler,
+    ) -> Result<(), SessionError> {
+        let action = agent.select_move(&game.board, game.bw);
+        if let Some(action) = action {
+            game.board = action.board;
+        }
+        let pos = action.map(|a| a.at);
+        self.write_cmd(writer, &Cmd::Move(pos))?;
+        handler.on_move(game.bw, pos, &game.board);
+        Ok(())
+    }
 }
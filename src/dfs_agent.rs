@@ -1,41 +1,126 @@
-use crate::board::{Action, Board, Player, Pos};
+use crate::board::{Action, Board, Player, SquareSet};
 use crate::agent::Agent;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Fixed-depth negamax search with alpha-beta pruning.
+///
+/// Leaves are scored by stable discs, corners and mobility; finished games by their final
+/// disc difference, far above any heuristic score.
 pub struct DfsAgent {
     depth: usize,
+    stop: Arc<AtomicBool>,
 }
 impl DfsAgent {
     pub fn new(depth: usize) -> Self {
-        Self { depth }
+        Self {
+            depth,
+            stop: Arc::default(),
+        }
+    }
+    pub(crate) fn evaluate(board: &Board, p: Player) -> i32 {
+        let o = p.opponent();
+        if board.is_final() {
+            return 1000 * (board.count(p) as i32 - board.count(o) as i32);
+        }
+        let stable = board.stable_discs(p).len() as i32 - board.stable_discs(o).len() as i32;
+        let corners = (board.get_disks(p) & SquareSet::CORNERS).len() as i32
+            - (board.get_disks(o) & SquareSet::CORNERS).len() as i32;
+        let mobility = board.valid_moves_fast(p).len() as i32 - board.valid_moves_fast(o).len() as i32;
+        10 * stable + 5 * corners + mobility
     }
-    fn dfs(&self, board: &Board, p: Player, depth: usize) -> i32 {
-        if depth == 0 {
-            return board.count(p) as i32;
+    /// Score of `board` for `p`, to move, searched `depth` plies deep within `(alpha, beta)`.
+    fn dfs(&self, board: &Board, p: Player, depth: usize, mut alpha: i32, beta: i32) -> i32 {
+        if depth == 0 || board.is_final() {
+            return Self::evaluate(board, p);
+        }
+        if self.stop.load(Ordering::Relaxed) {
+            return 0;
+        }
+        let mut moves = board.valid_moves(p).peekable();
+        if moves.peek().is_none() {
+            return -self.dfs(board, p.opponent(), depth - 1, -beta, -alpha);
         }
-        let mut best_score = -1000;
-        for Action{at, board} in board.valid_moves(p) {
-            let score = -self.dfs(&board, p.opponent(), depth - 1);
-            if score > best_score {
-                best_score = score;
+        let mut best_score = i32::MIN + 1;
+        for Action { board, .. } in moves {
+            let score = -self.dfs(&board, p.opponent(), depth - 1, -beta, -alpha);
+            best_score = best_score.max(score);
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
             }
         }
         best_score
     }
 }
 impl Agent for DfsAgent {
-    fn opponent_move_callback(&mut self, _action: Option<Action>) {
-        todo!()
-    }
-    fn select_move(&mut self, _board: &Board, _p: Player) -> Option<Action> {
-        unimplemented!()
-        // let mut best_action = None;
-        // let mut best_score = -1000;
-        // for Action{at, board} in board.valid_moves(p) {
-        //     let score = self.dfs(&board, p, self.depth);
-        //     if score > best_score {
-        //         best_action = Some(action);
-        //         best_score = score;
-        //     }
-        // }
-        // best_action
-    }
-}
\ No newline at end of file
+    fn set_stop(&mut self, stop: Arc<AtomicBool>) {
+        self.stop = stop;
+    }
+    fn select_move(&mut self, board: &Board, p: Player) -> Option<Action> {
+        let mut best_action = None;
+        let mut alpha = i32::MIN + 1;
+        for action in board.valid_moves(p) {
+            let score = -self.dfs(&action.board, p.opponent(), self.depth.saturating_sub(1), i32::MIN + 1, -alpha);
+            if best_action.is_none() || score > alpha {
+                best_action = Some(action);
+                alpha = score;
+            }
+        }
+        best_action
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{RandomAgent, SeededRandomAgent};
+    use crate::match_runner::{play_match, MatchConfig};
+
+    /// Plain negamax without pruning, to check that pruning does not change the score.
+    fn negamax(board: &Board, p: Player, depth: usize) -> i32 {
+        if depth == 0 || board.is_final() {
+            return DfsAgent::evaluate(board, p);
+        }
+        let scores: Vec<_> = board.valid_moves(p).map(|a| -negamax(&a.board, p.opponent(), depth - 1)).collect();
+        match scores.into_iter().max() {
+            Some(score) => score,
+            None => -negamax(board, p.opponent(), depth - 1),
+        }
+    }
+
+    #[test]
+    fn test_pruning_keeps_score() {
+        let agent = DfsAgent::new(4);
+        let mut board = Board::initial();
+        let mut p = Player::Black;
+        for _ in 0..20 {
+            for depth in 1..=4 {
+                assert_eq!(agent.dfs(&board, p, depth, i32::MIN + 1, i32::MAX), negamax(&board, p, depth));
+            }
+            if let Some(action) = RandomAgent.select_move(&board, p) {
+                board = action.board;
+            }
+            p.flip();
+        }
+    }
+
+    #[test]
+    fn test_stop() {
+        // far too deep to finish, unless stopped
+        let mut agent = DfsAgent::new(40);
+        let stop = Arc::new(AtomicBool::new(false));
+        agent.set_stop(stop.clone());
+        let searcher = std::thread::spawn(move || agent.select_move(&Board::initial(), Player::Black));
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        stop.store(true, Ordering::Relaxed);
+        assert!(searcher.join().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_beats_random() {
+        let mut random = SeededRandomAgent::new(36);
+        let stats = play_match(&mut DfsAgent::new(2), &mut random, &MatchConfig::new(10), |_, _| ());
+        assert!(stats.wins >= 8, "{}", stats);
+    }
+}
@@ -0,0 +1,416 @@
+//! Level-by-level enumeration of the positions reachable from a root, counted up to symmetry.
+//!
+//! Each level holds the distinct positions reachable in exactly `depth` plies (a pass counts as
+//! a ply, as in [`perft`](crate::perft::perft)), canonicalized under the 8 symmetries of the
+//! board. Levels are expanded in parallel, and when a spill directory is set the next level is
+//! written to disk as sorted runs whenever it outgrows the memory limit, then merged.
+use crate::board::{Board, Player, SquareSet};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::PathBuf;
+
+/// (black disks, white disks, white to move) of a canonical position.
+type Key = (u64, u64, bool);
+const KEY_BYTES: usize = 17;
+/// Most legal moves of any position, which bounds the children of a chunk of parents.
+const MAX_MOVES: usize = 33;
+
+fn key(board: &Board, p: Player) -> Key {
+    let c = board.canonical();
+    (
+        c.get_disks(Player::Black).bits(),
+        c.get_disks(Player::White).bits(),
+        p == Player::White,
+    )
+}
+
+fn position(k: Key) -> (Board, Player) {
+    let p = if k.2 { Player::White } else { Player::Black };
+    (Board::from_disks(SquareSet::new(k.0), SquareSet::new(k.1)), p)
+}
+
+fn write_key(w: &mut impl Write, k: Key) -> io::Result<()> {
+    w.write_all(&k.0.to_le_bytes())?;
+    w.write_all(&k.1.to_le_bytes())?;
+    w.write_all(&[k.2 as u8])
+}
+
+fn read_key(r: &mut impl Read) -> io::Result<Option<Key>> {
+    let mut buf = [0u8; KEY_BYTES];
+    match r.read_exact(&mut buf) {
+        Ok(()) => (),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let black = u64::from_le_bytes(buf[0..8].try_into().unwrap());
+    let white = u64::from_le_bytes(buf[8..16].try_into().unwrap());
+    Ok(Some((black, white, buf[16] != 0)))
+}
+
+/// Counts of one level of the enumeration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelStats {
+    pub depth: u32,
+    /// Distinct positions up to symmetry.
+    pub unique: u64,
+    /// How many of them are finished games.
+    pub terminal: u64,
+    /// Number of sorted runs written to disk while building the level.
+    pub spilled_runs: usize,
+}
+
+enum Frontier {
+    Memory(Vec<Key>),
+    Disk(PathBuf),
+}
+
+impl Frontier {
+    fn for_each_chunk(
+        &self,
+        chunk_size: usize,
+        mut f: impl FnMut(&[Key]) -> io::Result<()>,
+    ) -> io::Result<()> {
+        match self {
+            Frontier::Memory(keys) => keys.chunks(chunk_size).try_for_each(f),
+            Frontier::Disk(path) => {
+                let mut reader = BufReader::new(File::open(path)?);
+                let mut chunk = Vec::with_capacity(chunk_size);
+                while let Some(k) = read_key(&mut reader)? {
+                    chunk.push(k);
+                    if chunk.len() == chunk_size {
+                        f(&chunk)?;
+                        chunk.clear();
+                    }
+                }
+                if !chunk.is_empty() {
+                    f(&chunk)?;
+                }
+                Ok(())
+            }
+        }
+    }
+    fn remove(self) -> io::Result<()> {
+        match self {
+            Frontier::Memory(_) => Ok(()),
+            Frontier::Disk(path) => std::fs::remove_file(path),
+        }
+    }
+}
+
+/// Enumerates positions level by level; see the module documentation.
+#[derive(Debug, Clone)]
+pub struct Enumerator {
+    threads: usize,
+    memory_limit: usize,
+    spill_dir: Option<PathBuf>,
+}
+
+impl Default for Enumerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Enumerator {
+    pub fn new() -> Self {
+        Self {
+            threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            memory_limit: 1 << 24,
+            spill_dir: None,
+        }
+    }
+    /// Number of worker threads used to expand a level.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+    /// Number of positions of the next level kept in memory before a run is spilled to disk.
+    ///
+    /// Parents are then expanded in chunks whose children fit in the same limit, so at most
+    /// twice this many positions are in memory at once besides the current level. Only has an
+    /// effect together with [`Enumerator::spill_dir`].
+    pub fn memory_limit(mut self, memory_limit: usize) -> Self {
+        self.memory_limit = memory_limit.max(1);
+        self
+    }
+    /// Directory for the temporary run and frontier files; without one nothing is spilled.
+    pub fn spill_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.spill_dir = Some(dir.into());
+        self
+    }
+
+    /// Enumerates the positions up to `max_depth` plies from `board` with `p` to move, calling
+    /// `on_level` as soon as each level is complete.
+    ///
+    /// Stops early if a level is empty, i.e. every game has finished.
+    pub fn run(
+        &self,
+        board: &Board,
+        p: Player,
+        max_depth: u32,
+        mut on_level: impl FnMut(&LevelStats),
+    ) -> io::Result<Vec<LevelStats>> {
+        if let Some(dir) = &self.spill_dir {
+            std::fs::create_dir_all(dir)?;
+        }
+        let root = LevelStats {
+            depth: 0,
+            unique: 1,
+            terminal: board.is_final() as u64,
+            spilled_runs: 0,
+        };
+        on_level(&root);
+        let mut levels = vec![root];
+        let mut frontier = Frontier::Memory(vec![key(board, p)]);
+        let chunk_size = match self.spill_dir {
+            Some(_) => (self.memory_limit / MAX_MOVES).clamp(1, 1 << 16),
+            None => (self.threads * 4096).max(1 << 16),
+        };
+        for depth in 1..=max_depth {
+            let mut builder = LevelBuilder::new(self, depth);
+            frontier.for_each_chunk(chunk_size, |chunk| builder.push(self.expand(chunk)))?;
+            let (next, stats) = builder.finish()?;
+            frontier.remove()?;
+            frontier = next;
+            on_level(&stats);
+            levels.push(stats);
+            if stats.unique == 0 {
+                break;
+            }
+        }
+        frontier.remove()?;
+        Ok(levels)
+    }
+
+    /// Children of every position of `chunk`, sorted and deduplicated per thread.
+    fn expand(&self, chunk: &[Key]) -> Vec<Key> {
+        let part = chunk.len().div_ceil(self.threads).max(1);
+        std::thread::scope(|s| {
+            let handles: Vec<_> = chunk
+                .chunks(part)
+                .map(|keys| s.spawn(move || children(keys)))
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().expect("enumeration worker panicked"))
+                .collect()
+        })
+    }
+}
+
+fn children(keys: &[Key]) -> Vec<Key> {
+    let mut res = Vec::with_capacity(keys.len() * 8);
+    for &k in keys {
+        let (board, p) = position(k);
+        let o = p.opponent();
+        let moves = board.moves(p);
+        if moves.is_empty() {
+            if !board.moves(o).is_empty() {
+                res.push(key(&board, o));
+            }
+            continue;
+        }
+        for pos in moves {
+            let mut child = board;
+            child.make_move(p, pos);
+            res.push(key(&child, o));
+        }
+    }
+    res.sort_unstable();
+    res.dedup();
+    res
+}
+
+fn is_terminal(k: Key) -> bool {
+    position(k).0.is_final()
+}
+
+/// Collects the (duplicated) children of one level, spilling sorted runs when needed.
+struct LevelBuilder<'a> {
+    enumerator: &'a Enumerator,
+    depth: u32,
+    buffer: Vec<Key>,
+    runs: Vec<PathBuf>,
+}
+
+impl<'a> LevelBuilder<'a> {
+    fn new(enumerator: &'a Enumerator, depth: u32) -> Self {
+        Self {
+            enumerator,
+            depth,
+            buffer: Vec::new(),
+            runs: Vec::new(),
+        }
+    }
+    fn push(&mut self, keys: Vec<Key>) -> io::Result<()> {
+        let limit = self.enumerator.memory_limit;
+        if self.enumerator.spill_dir.is_none() {
+            self.buffer.extend(keys);
+            return Ok(());
+        }
+        // fill the buffer up to the limit at most, making room whenever it is full
+        let mut keys = &keys[..];
+        while !keys.is_empty() {
+            if self.buffer.len() >= limit {
+                self.buffer.sort_unstable();
+                self.buffer.dedup();
+                // keep merging in memory while deduplication frees enough room
+                if self.buffer.len() > limit / 2 {
+                    self.spill()?;
+                }
+            }
+            let n = (limit - self.buffer.len()).min(keys.len());
+            self.buffer.extend_from_slice(&keys[..n]);
+            keys = &keys[n..];
+        }
+        Ok(())
+    }
+    fn path(&self, name: &str) -> PathBuf {
+        let dir = self.enumerator.spill_dir.as_ref().unwrap();
+        dir.join(format!("level{}-{}.bin", self.depth, name))
+    }
+    fn spill(&mut self) -> io::Result<()> {
+        let path = self.path(&format!("run{}", self.runs.len()));
+        let mut writer = BufWriter::new(File::create(&path)?);
+        for &k in &self.buffer {
+            write_key(&mut writer, k)?;
+        }
+        writer.flush()?;
+        self.buffer.clear();
+        self.runs.push(path);
+        Ok(())
+    }
+    fn finish(mut self) -> io::Result<(Frontier, LevelStats)> {
+        self.buffer.sort_unstable();
+        self.buffer.dedup();
+        let mut stats = LevelStats {
+            depth: self.depth,
+            unique: 0,
+            terminal: 0,
+            spilled_runs: self.runs.len(),
+        };
+        if self.runs.is_empty() {
+            stats.unique = self.buffer.len() as u64;
+            stats.terminal = self.buffer.iter().filter(|&&k| is_terminal(k)).count() as u64;
+            return Ok((Frontier::Memory(self.buffer), stats));
+        }
+        // k-way merge of the runs and what is left in memory
+        let mut readers = self
+            .runs
+            .iter()
+            .map(|path| File::open(path).map(BufReader::new))
+            .collect::<io::Result<Vec<_>>>()?;
+        let mut heap = BinaryHeap::new();
+        for (i, reader) in readers.iter_mut().enumerate() {
+            if let Some(k) = read_key(reader)? {
+                heap.push(Reverse((k, i)));
+            }
+        }
+        let mut memory = self.buffer.iter().copied();
+        let memory_source = readers.len();
+        if let Some(k) = memory.next() {
+            heap.push(Reverse((k, memory_source)));
+        }
+        let path = self.path("frontier");
+        let mut writer = BufWriter::new(File::create(&path)?);
+        let mut last = None;
+        while let Some(Reverse((k, i))) = heap.pop() {
+            if last != Some(k) {
+                write_key(&mut writer, k)?;
+                stats.unique += 1;
+                stats.terminal += is_terminal(k) as u64;
+                last = Some(k);
+            }
+            let next = if i == memory_source {
+                memory.next()
+            } else {
+                read_key(&mut readers[i])?
+            };
+            if let Some(k) = next {
+                heap.push(Reverse((k, i)));
+            }
+        }
+        writer.flush()?;
+        for run in &self.runs {
+            std::fs::remove_file(run)?;
+        }
+        Ok((Frontier::Disk(path), stats))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn naive(max_depth: u32) -> Vec<u64> {
+        let mut level = HashSet::from([key(&Board::initial(), Player::Black)]);
+        let mut res = vec![1];
+        for _ in 0..max_depth {
+            level = children(&level.into_iter().collect::<Vec<_>>()).into_iter().collect();
+            res.push(level.len() as u64);
+        }
+        res
+    }
+
+    #[test]
+    fn test_enumerate() {
+        let expected = naive(7);
+        assert_eq!(&expected[..4], &[1, 1, 3, 14]);
+        let mut reported = Vec::new();
+        let levels = Enumerator::new()
+            .threads(3)
+            .run(&Board::initial(), Player::Black, 7, |s| reported.push(*s))
+            .unwrap();
+        assert_eq!(levels, reported);
+        assert_eq!(levels.iter().map(|s| s.unique).collect::<Vec<_>>(), expected);
+        assert!(levels.iter().all(|s| s.spilled_runs == 0));
+    }
+
+    #[test]
+    fn test_enumerate_spill() {
+        let dir = std::env::temp_dir().join(format!("reversi-enumerate-{}", std::process::id()));
+        let in_memory = Enumerator::new().threads(2).run(&Board::initial(), Player::Black, 6, |_| {}).unwrap();
+        let spilled = Enumerator::new()
+            .threads(2)
+            .memory_limit(100)
+            .spill_dir(&dir)
+            .run(&Board::initial(), Player::Black, 6, |_| {})
+            .unwrap();
+        assert!(spilled.iter().any(|s| s.spilled_runs > 0));
+        for (a, b) in in_memory.iter().zip(&spilled) {
+            assert_eq!((a.depth, a.unique, a.terminal), (b.depth, b.unique, b.terminal));
+        }
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let dir = std::env::temp_dir().join(format!("reversi-limit-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let enumerator = Enumerator::new().memory_limit(100).spill_dir(&dir);
+        let mut builder = LevelBuilder::new(&enumerator, 1);
+        for i in 0..10 {
+            builder.push((0..1000).map(|k| (k, (i + 1) << 32, false)).collect()).unwrap();
+            assert!(builder.buffer.len() <= 100);
+        }
+        assert!(builder.runs.len() >= 99);
+        let (frontier, stats) = builder.finish().unwrap();
+        assert_eq!(stats.unique, 10_000);
+        frontier.remove().unwrap();
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_enumerate_game_over() {
+        // a position where neither side can move has no successors
+        let board = Board::from_disks(SquareSet::FULL, SquareSet::EMPTY);
+        let levels = Enumerator::new().run(&board, Player::White, 3, |_| {}).unwrap();
+        assert_eq!(levels.len(), 2);
+        assert_eq!((levels[0].unique, levels[0].terminal), (1, 1));
+        assert_eq!(levels[1].unique, 0);
+    }
+}
@@ -30,12 +30,12 @@ fn gen_random_self_play<'a, 'b, 'c>(n: usize, dest: &str) -> std::io::Result<()>
                 writeln!(
                     writer,
                     "{},{},{},{},{},{}",
-                    b.get_disks(p),
-                    b.get_disks(p.opponent()),
+                    b.get_disks(p).bits(),
+                    b.get_disks(p.opponent()).bits(),
                     a.at as u8,
-                    b.valid_moves_fast(p),
-                    a.board.get_disks(p),
-                    a.board.get_disks(p.opponent())
+                    b.valid_moves_fast(p).bits(),
+                    a.board.get_disks(p).bits(),
+                    a.board.get_disks(p.opponent()).bits()
                 ).unwrap()
             });
         };
@@ -58,8 +58,8 @@ fn gen_boards_from_games(games: Vec<wthor::Game>, dest: &str) -> std::io::Result
         );
         let mut prev = states.next().unwrap();
         for (player, board, last_pos) in states {
-            write!(writer, "{},", prev.1.get_disks(prev.0))?;
-            write!(writer, "{},", prev.1.get_disks(prev.0.opponent()))?;
+            write!(writer, "{},", prev.1.get_disks(prev.0).bits())?;
+            write!(writer, "{},", prev.1.get_disks(prev.0.opponent()).bits())?;
             match last_pos {
                 Ok(last_pos) => {
                     write!(writer, "{},", last_pos as u64)?;
@@ -74,10 +74,10 @@ fn gen_boards_from_games(games: Vec<wthor::Game>, dest: &str) -> std::io::Result
             }
             // write_disks!(prev.1.valid_moves_fast(prev.0));
             // writer.write(b",")?;
-            write!(writer, "{},", prev.1.valid_moves_fast(prev.0))?;
+            write!(writer, "{},", prev.1.valid_moves_fast(prev.0).bits())?;
             // write!(writer, "{},", player as u8)?;
-            write!(writer, "{},", board.get_disks(prev.0))?;
-            write!(writer, "{}", board.get_disks(prev.0.opponent()))?;
+            write!(writer, "{},", board.get_disks(prev.0).bits())?;
+            write!(writer, "{}", board.get_disks(prev.0.opponent()).bits())?;
             // write_disks!(board.get_disks(Player::Black));
             // writer.write(b",")?;
             // write_disks!(board.get_disks(Player::White));
@@ -109,22 +109,22 @@ fn generate_action_for_random_board() -> std::io::Result<()>{
                 let b = tmp ^ w;
                 write!(writer, "{},", b)?;
                 write!(writer, "{},", w)?;
-                let board = Board::from_disks(b, w);
+                let board = Board::from_disks(b.into(), w.into());
                 let moves = 0xffff_ffff_ffff_ffffu64 ^ (b | w);
                 // let moves = board.valid_moves_fast(Player::Black);
                 match board.valid_moves(Player::Black).choose(&mut rng) {
                     Some(action) => {
                         write!(writer, "{},", action.at as u64)?;
                         write!(writer, "{},", moves)?;
-                        write!(writer, "{},", action.board.get_disks(Player::Black))?;
-                        write!(writer, "{}", action.board.get_disks(Player::White))?;
+                        write!(writer, "{},", action.board.get_disks(Player::Black).bits())?;
+                        write!(writer, "{}", action.board.get_disks(Player::White).bits())?;
                         writer.write(b"\n")?;
                     }
                     None => {
                         write!(writer, "64,")?;
                         write!(writer, "{},", moves)?;
-                        write!(writer, "{},", board.get_disks(Player::Black))?;
-                        write!(writer, "{}", board.get_disks(Player::White))?;
+                        write!(writer, "{},", board.get_disks(Player::Black).bits())?;
+                        write!(writer, "{}", board.get_disks(Player::White).bits())?;
                         writer.write(b"\n")?;
                     }
                 }
@@ -143,7 +143,7 @@ fn check_game(games: &wthor::Game) {
     let mut p = Player::Black;
     for (_i, pos) in moves.iter().enumerate() {
         let m = Pos::from_wthor_position(pos);
-        if board.valid_moves_fast(p) == 0 {
+        if board.valid_moves_fast(p).is_empty() {
             p.flip();
         }
         let valid_moves: Vec<_> = board.valid_moves(p).collect();
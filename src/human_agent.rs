@@ -0,0 +1,228 @@
+use crate::agent::{Agent, GameEnd};
+use crate::board::{Action, Board, Player, Pos};
+use crate::dfs_agent::DfsAgent;
+use crate::render::{legal_moves, symbol, View};
+use std::io::{BufRead, IsTerminal, Write};
+
+/// Asks a human for moves, showing the board with legal moves and the opponent's last move.
+///
+/// A move typed in is pending until confirmed with an empty line or `ok`, and `undo` takes it
+/// back; a move once confirmed is played and cannot be undone. `hint` asks the hint agent.
+/// Without a legal move, `pass` or an empty line passes, or the pass is automatic when moves
+/// need no confirmation. If the input ends, the hint agent plays instead.
+pub struct HumanAgent<R, W> {
+    input: R,
+    output: W,
+    color: bool,
+    confirm: bool,
+    hint_agent: Box<dyn Agent>,
+    /// The board after our last move, to tell which discs the opponent flipped.
+    after_own_move: Option<Board>,
+    opponent_move: Option<Option<Pos>>,
+}
+
+impl HumanAgent<std::io::BufReader<std::io::Stdin>, std::io::Stderr> {
+    /// Reads moves from stdin and talks on stderr, leaving stdout to a protocol. Stdin is
+    /// only locked while reading, but should not be read by anything else.
+    pub fn stdin() -> Self {
+        let color = std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+        Self::new(std::io::BufReader::new(std::io::stdin()), std::io::stderr()).color(color)
+    }
+}
+
+impl<R: BufRead, W: Write> HumanAgent<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self {
+            input,
+            output,
+            color: false,
+            confirm: true,
+            hint_agent: Box::new(DfsAgent::new(4)),
+            after_own_move: None,
+            opponent_move: None,
+        }
+    }
+
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Whether moves wait for confirmation, and can be undone, before being played.
+    pub fn confirm(mut self, confirm: bool) -> Self {
+        self.confirm = confirm;
+        self
+    }
+
+    pub fn hint_agent(mut self, agent: Box<dyn Agent>) -> Self {
+        self.hint_agent = agent;
+        self
+    }
+
+    fn view(&self, board: &Board, p: Player) -> View {
+        let mut view = View::new(*board).color(self.color);
+        if let Some(pos) = self.opponent_move {
+            let flips = match self.after_own_move {
+                Some(before) => before.get_disks(p) & board.get_disks(p.opponent()),
+                None => Default::default(),
+            };
+            view = view.last_move(p.opponent(), pos, flips);
+        }
+        view
+    }
+
+    fn read_line(&mut self) -> std::io::Result<Option<String>> {
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line.trim().to_ascii_lowercase()))
+    }
+
+    /// Asks until a move is played, `None` once the input is over.
+    fn ask(&mut self, board: &Board, p: Player) -> std::io::Result<Option<Action>> {
+        let moves: Vec<Action> = board.valid_moves(p).collect();
+        writeln!(self.output, "\n{}", self.view(board, p).hints(p))?;
+        let mut pending: Option<Action> = None;
+        loop {
+            match pending {
+                Some(action) => write!(self.output, "{} {}? [enter] to play, undo > ", symbol(p), action.at)?,
+                None => write!(self.output, "{} to move (e.g. {}), hint, pass > ", symbol(p), moves[0].at)?,
+            }
+            self.output.flush()?;
+            let Some(line) = self.read_line()? else {
+                return Ok(None);
+            };
+            match (line.as_str(), pending) {
+                ("" | "ok", Some(action)) => return Ok(Some(action)),
+                ("undo" | "u", Some(_)) => pending = None,
+                ("undo" | "u", None) => writeln!(self.output, "Nothing to undo")?,
+                ("hint" | "h", _) => match self.hint_agent.select_move(board, p) {
+                    Some(action) => writeln!(self.output, "Hint: {}", action.at)?,
+                    None => writeln!(self.output, "Hint: pass")?,
+                },
+                ("pass", _) => writeln!(self.output, "You can only pass without a legal move")?,
+                ("", None) => {}
+                (s, _) => match Pos::try_from(s).ok().and_then(|pos| moves.iter().find(|a| a.at == pos)) {
+                    Some(&action) if self.confirm => {
+                        let view = View::new(action.board).color(self.color);
+                        writeln!(self.output, "\n{}", view.last_move(p, Some(action.at), board.flips(p, action.at)))?;
+                        pending = Some(action);
+                    }
+                    Some(&action) => return Ok(Some(action)),
+                    None => writeln!(self.output, "{:?} is not a legal move; legal: {}", s, legal_moves(board, p))?,
+                },
+            }
+        }
+    }
+
+    /// Tells that `p` has to pass, and waits for `pass` when moves are confirmed.
+    fn ask_pass(&mut self, board: &Board, p: Player) -> std::io::Result<()> {
+        writeln!(self.output, "\n{}\n{} has no legal move and passes", self.view(board, p), symbol(p))?;
+        while self.confirm {
+            write!(self.output, "{} pass? [enter] to go on > ", symbol(p))?;
+            self.output.flush()?;
+            match self.read_line()?.as_deref() {
+                None | Some("" | "pass" | "ok") => break,
+                Some("hint" | "h") => writeln!(self.output, "Hint: pass")?,
+                Some(s) => writeln!(self.output, "{:?} is not a legal move; legal: none, pass", s)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead, W: Write> Agent for HumanAgent<R, W> {
+    fn initialize(&mut self) {
+        self.after_own_move = None;
+        self.opponent_move = None;
+        self.hint_agent.initialize();
+    }
+    fn opponent_move_callback(&mut self, action: Option<Action>) {
+        self.opponent_move = Some(action.map(|a| a.at));
+    }
+    fn select_move(&mut self, board: &Board, p: Player) -> Option<Action> {
+        let action = if board.valid_moves_fast(p).is_empty() {
+            if let Err(err) = self.ask_pass(board, p) {
+                eprintln!("cannot ask for a pass: {}", err);
+            }
+            None
+        } else {
+            match self.ask(board, p) {
+                Ok(Some(action)) => Some(action),
+                result => {
+                    if let Err(err) = result {
+                        eprintln!("cannot ask for a move: {}", err);
+                    }
+                    let _ = writeln!(self.output, "\nNo more input, the hint agent plays");
+                    self.hint_agent.select_move(board, p)
+                }
+            }
+        };
+        self.after_own_move = Some(action.map_or(*board, |a| a.board));
+        self.opponent_move = None;
+        action
+    }
+    fn game_end(&mut self, end: &GameEnd) {
+        let _ = writeln!(self.output, "\n{:?} {}-{} ({})", end.result, end.my_score, end.opponent_score, end.reason);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::RandomAgent;
+    use crate::simulate::simulate;
+
+    fn ask(input: &str, board: &Board, p: Player) -> (Option<Pos>, String) {
+        let mut output = Vec::new();
+        let action = HumanAgent::new(input.as_bytes(), &mut output).select_move(board, p);
+        (action.map(|a| a.at), String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_confirm_and_undo() {
+        let board = Board::initial();
+        let (pos, output) = ask("a1\nd3\nundo\nhint\nc4\n\n", &board, Player::Black);
+        assert_eq!(pos, Some(Pos::C4));
+        assert!(output.contains("\"a1\" is not a legal move; legal: D3 C4 F5 E6"), "{}", output);
+        assert!(output.contains("● D3? [enter] to play, undo"));
+        assert!(output.contains("Hint: "));
+        let (pos, output) = ask("pass\nundo\nf5\nok\n", &board, Player::Black);
+        assert_eq!(pos, Some(Pos::F5));
+        assert!(output.contains("You can only pass without a legal move"));
+        assert!(output.contains("Nothing to undo"));
+    }
+
+    #[test]
+    fn test_pass_and_end_of_input() {
+        use crate::board::SquareSet;
+        // Black on C1 cannot move
+        let board = Board::from_disks(Pos::C1.into(), SquareSet::from(Pos::A1) | Pos::B1);
+        let (pos, output) = ask("d1\nhint\npass\n", &board, Player::Black);
+        assert_eq!(pos, None);
+        assert!(output.contains("● has no legal move and passes"));
+        assert!(output.contains("\"d1\" is not a legal move; legal: none, pass"), "{}", output);
+        assert!(output.contains("Hint: pass"));
+        let (pos, _) = ask("", &board, Player::Black);
+        assert_eq!(pos, None);
+        // a pending move is not played without confirmation
+        let (pos, output) = ask("d1\n", &board, Player::White);
+        assert_eq!(pos, Some(Pos::D1));
+        assert!(output.contains("No more input"));
+    }
+
+    #[test]
+    fn test_simulate() {
+        // every square in reading order, so the first legal one gets played
+        let squares: String = Pos::iter().map(|pos| format!("{}\n", pos)).collect();
+        let input = squares.repeat(60);
+        let mut output = Vec::new();
+        let mut human = HumanAgent::new(input.as_bytes(), &mut output).confirm(false);
+        let record = simulate(Board::initial(), Player::Black, &mut human, &mut RandomAgent, &mut |_, _, _| {});
+        assert!(record.final_board.is_final());
+        let output = String::from_utf8(output).unwrap();
+        assert!(!output.contains("No more input"));
+        assert!(output.contains("○ played"));
+    }
+}
@@ -1,11 +1,9 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use std::thread;
-use reversi::{
-    agent::{Agent, OneStepLookaheadAgent, RandomAgent},
-    board::{Action, Board, Player, Pos},
-    command::SessionError,
-    simulate,
-};
+use reversi::agent::GameEnd;
+use reversi::command::{Milliseconds, Reconnect, Recovery, SessionError, SessionHandler, Wl};
+use std::time::Duration;
+use reversi::registry::AgentSpec;
 
 #[derive(Parser, Debug)]
 #[command(about)]
@@ -25,61 +23,133 @@ struct Cli {
     )]
     port: u16,
 
-    #[arg(long, default_value_t = 10000u32, help = "number of simulations", value_parser = clap::value_parser!(u32).range(100..))]
+    #[arg(long, default_value_t = 10000u32, help = "number of simulations of the default MCTS agent", value_parser = clap::value_parser!(u32).range(100..))]
     n_simulations: u32,
+    #[arg(long, help = "agent spec, e.g. mcts:sims=20000,c=1.2 or alphabeta:depth=6 [default: MCTS with --n-simulations]")]
+    agent: Option<AgentSpec>,
     #[arg(short, default_value = "Anon.", help="player name")]
     name: String,
     #[arg(short, default_value_t = false, help = "verbose mode")]
     verbose: bool,
+    #[arg(long, value_enum, default_value_t = OnError::Resync, help = "what to do when the server sends something unexpected")]
+    on_error: OnError,
+    #[arg(long, default_value_t = false, help = "talk to the server over stdin and stdout instead of connecting")]
+    stdio: bool,
+    #[arg(long, default_value_t = 5, help = "reconnection attempts in a row after losing the server, 0 to exit")]
+    reconnect: u32,
+    #[arg(long, default_value_t = 500, help = "milliseconds before the first reconnection attempt, doubling after each")]
+    reconnect_delay: u64,
+    #[cfg(feature = "async")]
+    #[arg(long = "async", default_value_t = false, conflicts_with = "stdio", help = "search on a worker thread while the connection is served by tokio")]
+    use_async: bool,
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum OnError {
+    /// Stop the client
+    Abort,
+    /// Log the error and carry on, following the server's board
+    Resync,
+    /// Log the error and sit out the rest of the game
+    Abandon,
+}
+
+impl From<OnError> for Recovery {
+    fn from(on_error: OnError) -> Self {
+        match on_error {
+            OnError::Abort => Recovery::Abort,
+            OnError::Resync => Recovery::Resync,
+            OnError::Abandon => Recovery::AbandonGame,
+        }
+    }
+}
 
 
+/// Keeps the results of the games played and reports them as they end.
+#[derive(Default)]
+struct Scoreboard {
+    verbose: bool,
+    wins: u32,
+    losses: u32,
+    ties: u32,
+    reconnections: u32,
+}
+
+impl SessionHandler for Scoreboard {
+    fn on_ack(&mut self, remaining_time: Milliseconds) {
+        if self.verbose {
+            eprintln!("{:.1}s left", remaining_time.0 as f64 / 1000.0);
+        }
+    }
+    fn on_end(&mut self, end: &GameEnd) {
+        match end.result {
+            Wl::Win => self.wins += 1,
+            Wl::Lose => self.losses += 1,
+            Wl::Tie => self.ties += 1,
+        }
+        eprintln!(
+            "{:?} {}-{} ({}), {} wins, {} losses, {} ties so far",
+            end.result, end.my_score, end.opponent_score, end.reason, self.wins, self.losses, self.ties
+        );
+    }
+    fn on_reconnect(&mut self, _attempt: u32, _error: &std::io::Error) {
+        self.reconnections += 1;
+    }
+}
 
 fn main() -> Result<(), SessionError>{
     let cli = Cli::parse();
-    eprintln!("Connecting to {}:{}...", cli.host, cli.port);
-    let stream = std::net::TcpStream::connect((cli.host, cli.port))?;
+    let policy = Reconnect::new(cli.reconnect).initial_delay(Duration::from_millis(cli.reconnect_delay));
+    let (host, port, stdio) = (cli.host, cli.port, cli.stdio);
+    let connect = {
+        let host = host.clone();
+        move || {
+            eprintln!("Connecting to {}:{}...", host, port);
+            std::net::TcpStream::connect((host.as_str(), port))
+        }
+    };
     use reversi::command::Session;
     // let black = reversi::agent::RandomAgent;
     
-    let mut session = Session::new(cli.name);
+    let spec = cli.agent.unwrap_or(AgentSpec::Mcts {
+        n_simulations: cli.n_simulations,
+        c: AgentSpec::DEFAULT_C,
+    });
+    if stdio && spec == AgentSpec::Human {
+        eprintln!("--stdio needs stdin for the server, it cannot be used with --agent human");
+        std::process::exit(2);
+    }
+    eprintln!("Playing as {}", spec);
+    let mut session = Session::new(cli.name).recovery(cli.on_error.into());
+    #[cfg(feature = "async")]
+    if cli.use_async {
+        use reversi::async_session::{AsyncOptions, Searcher};
+        let mut scoreboard = Scoreboard { verbose: cli.verbose, ..Default::default() };
+        let runtime = tokio::runtime::Runtime::new()?;
+        let result = runtime.block_on(async {
+            let mut searcher = Searcher::spawn(move || spec.build());
+            session
+                .launch_async_reconnecting(&mut searcher, (host, port), &policy, &AsyncOptions::default(), &mut scoreboard)
+                .await
+        });
+        if let Err(e) = result {
+            eprintln!("{}", e);
+        }
+        return Ok(());
+    }
     let handle = thread::spawn(move|| {
-        let mut white = reversi::mcts_agent::MCTSAgent::new(cli.n_simulations, 1.4);
-        let _ = session.launch(&mut white, &mut &stream).map_err(|e| {eprintln!("{}", e); e});
+        let mut agent = spec.build();
+        let mut scoreboard = Scoreboard { verbose: cli.verbose, ..Default::default() };
+        let result = if stdio {
+            session.run_with(&mut agent, std::io::stdin().lock(), std::io::stdout().lock(), &mut scoreboard)
+        } else {
+            session.launch_reconnecting(&mut agent, connect, &policy, &mut scoreboard)
+        };
+        let _ = result.map_err(|e| {eprintln!("{}", e); e});
+        if scoreboard.reconnections > 0 {
+            eprintln!("{} reconnections", scoreboard.reconnections);
+        }
     });
     handle.join().expect("The thread being joined has panicked");
-    return Ok(());
-    // let mut nblackwins = 0;
-    // let mut nwhitewins = 0;
-    // let mut total_steps = 0usize;
-
-    // for i in 1..=1000 {
-    //     let board = Board::initial();
-
-    //     let mut n_steps = 0 as usize;
-    //     match simulate!(board, &black, &white, &mut n_steps) {
-    //         Some(Player::Black) => nblackwins += 1,
-    //         Some(Player::White) => nwhitewins += 1,
-    //         None => (),
-    //     }
-    //     total_steps += n_steps;
-    //     if i % 1 == 0 {
-    //         let blackrate = nblackwins as f64 / (nblackwins + nwhitewins) as f64;
-    //         let whiterate = nwhitewins as f64 / (nblackwins + nwhitewins) as f64;
-    //         println!(
-    //             "\r{:5}: Black: {:5} ({:.2}%), White: {:5} ({:.2}%) Ave. steps: {:.4}",
-    //             i,
-    //             nblackwins,
-    //             blackrate * 100.0,
-    //             nwhitewins,
-    //             whiterate * 100.0,
-    //             total_steps as f64 / i as f64
-    //         );
-    //     }
-    // }
-    // println!("Black wins: {}", nblackwins);
-    // println!("White wins: {}", nwhitewins);
-    
     Ok(())
 }
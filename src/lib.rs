@@ -1,6 +1,22 @@
 pub mod agent;
 pub mod board;
+pub mod square_set;
+pub mod movegen;
 pub mod command;
 pub mod simulate;
 pub mod mcts_agent;
-pub mod dfs_agent;
\ No newline at end of file
+pub mod dfs_agent;
+pub mod perft;
+pub mod enumerate;
+pub mod stability;
+pub mod match_runner;
+pub mod tournament;
+pub mod sprt;
+pub mod registry;
+pub mod server;
+pub mod render;
+pub mod human_agent;
+pub mod analysis;
+pub mod annotate;
+#[cfg(feature = "async")]
+pub mod async_session;
@@ -0,0 +1,292 @@
+//! Plays many games between two agents and summarises the results.
+use crate::agent::Agent;
+use crate::board::{Board, Player};
+use crate::simulate::{simulate, GameRecord};
+use std::collections::HashSet;
+
+/// Settings of a match.
+#[derive(Debug, Clone)]
+pub struct MatchConfig {
+    pub n_games: usize,
+    /// Starting positions, each with Black to move. Every opening is played twice in a row,
+    /// once with each agent as Black, so a lopsided opening favours neither agent.
+    /// Empty means every game starts from [`Board::initial`].
+    pub openings: Vec<Board>,
+}
+
+impl MatchConfig {
+    pub fn new(n_games: usize) -> Self {
+        Self {
+            n_games,
+            openings: Vec::new(),
+        }
+    }
+    pub fn with_openings(mut self, openings: Vec<Board>) -> Self {
+        self.openings = openings;
+        self
+    }
+    /// Starting position and whether agent `a` plays Black in game `i`.
+    pub fn game(&self, i: usize) -> (Board, bool) {
+        let board = match self.openings.len() {
+            0 => Board::initial(),
+            n => self.openings[(i / 2) % n],
+        };
+        (board, i.is_multiple_of(2))
+    }
+}
+
+/// Distinct positions (up to symmetry) reached after `plies` plies from the initial position
+/// with Black to move, a pass counting as a ply.
+///
+/// # Panics
+///
+/// If `plies` is odd, as White is then to move in every position.
+pub fn openings_after(plies: u32) -> Vec<Board> {
+    assert!(plies.is_multiple_of(2), "openings after an odd number of plies have White to move");
+    let mut level = vec![(Board::initial(), Player::Black)];
+    for _ in 0..plies {
+        let mut seen = HashSet::new();
+        level = level
+            .iter()
+            .flat_map(|&(b, p)| {
+                let children: Vec<_> = b.valid_moves(p).map(|a| a.board).collect();
+                if children.is_empty() && !b.is_final() {
+                    vec![(b, p.opponent())]
+                } else {
+                    children.into_iter().map(|c| (c, p.opponent())).collect()
+                }
+            })
+            .filter(|&(b, p)| seen.insert((b.canonical(), p)))
+            .collect();
+    }
+    level.into_iter().filter(|&(_, p)| p == Player::Black).map(|(b, _)| b).collect()
+}
+
+/// Parses a ply count for [`openings_after`] on the command line, rejecting odd ones.
+pub fn parse_opening_plies(s: &str) -> Result<u32, String> {
+    let plies: u32 = s.parse().map_err(|e: std::num::ParseIntError| e.to_string())?;
+    match plies.is_multiple_of(2) {
+        true => Ok(plies),
+        false => Err(format!("{} is odd, openings need Black to move", plies)),
+    }
+}
+
+/// Parses an opening given as a move sequence such as `F5D6C3D3`.
+///
+/// Returns `None` if a move is illegal or the sequence leaves White to move.
+pub fn opening_from_moves(moves: &str) -> Option<Board> {
+    let record = GameRecord::from_moves(Board::initial(), Player::Black, moves).ok()?;
+    (record.to_move() == Player::Black).then_some(record.final_board)
+}
+
+/// Outcome of one game of a match, from the point of view of agent `a`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameSummary {
+    pub index: usize,
+    pub a_is_black: bool,
+    /// Discs of `a` minus discs of `b` at the end of the game.
+    pub margin: i32,
+    /// Number of moves played, passes excluded.
+    pub length: usize,
+}
+
+/// Estimated Elo difference with a 95% confidence interval.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Elo {
+    pub diff: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl Elo {
+    /// Elo difference matching an expected score in `[0, 1]`.
+    pub fn from_score(score: f64) -> f64 {
+        if score <= 0.0 {
+            return f64::NEG_INFINITY;
+        }
+        if score >= 1.0 {
+            return f64::INFINITY;
+        }
+        -400.0 * (1.0 / score - 1.0).log10()
+    }
+}
+
+impl std::fmt::Display for Elo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:+.1} [{:+.1}, {:+.1}]", self.diff, self.lower, self.upper)
+    }
+}
+
+/// Running totals of a match, from the point of view of agent `a`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MatchStats {
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+    pub total_margin: i64,
+    pub total_length: u64,
+}
+
+impl MatchStats {
+    pub fn add(&mut self, game: &GameSummary) {
+        match game.margin.signum() {
+            1 => self.wins += 1,
+            -1 => self.losses += 1,
+            _ => self.draws += 1,
+        }
+        self.total_margin += game.margin as i64;
+        self.total_length += game.length as u64;
+    }
+    pub fn games(&self) -> u32 {
+        self.wins + self.losses + self.draws
+    }
+    /// Points per game, a draw counting as half a point.
+    pub fn score(&self) -> f64 {
+        (self.wins as f64 + 0.5 * self.draws as f64) / self.games().max(1) as f64
+    }
+    pub fn average_margin(&self) -> f64 {
+        self.total_margin as f64 / self.games().max(1) as f64
+    }
+    pub fn average_length(&self) -> f64 {
+        self.total_length as f64 / self.games().max(1) as f64
+    }
+    /// Elo difference of `a` over `b`, with a 95% confidence interval from the normal
+    /// approximation of the per-game score.
+    pub fn elo(&self) -> Elo {
+        let n = self.games().max(1) as f64;
+        let s = self.score();
+        let variance = (self.wins as f64 * (1.0 - s).powi(2)
+            + self.draws as f64 * (0.5 - s).powi(2)
+            + self.losses as f64 * s.powi(2))
+            / n;
+        let margin = 1.96 * (variance / n).sqrt();
+        Elo {
+            diff: Elo::from_score(s),
+            lower: Elo::from_score(s - margin),
+            upper: Elo::from_score(s + margin),
+        }
+    }
+}
+
+impl std::fmt::Display for MatchStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "games: {}, +{} -{} ={}, score: {:.1}%, margin: {:+.2}, length: {:.1}, elo: {}",
+            self.games(),
+            self.wins,
+            self.losses,
+            self.draws,
+            100.0 * self.score(),
+            self.average_margin(),
+            self.average_length(),
+            self.elo()
+        )
+    }
+}
+
+/// Plays one game from `board` with Black to move, `a` playing Black if `a_is_black`.
+pub fn play_game(
+    a: &mut impl Agent,
+    b: &mut impl Agent,
+    board: Board,
+    a_is_black: bool,
+    index: usize,
+) -> GameSummary {
+    play_recorded_game(a, b, board, a_is_black, index).0
+}
+
+/// Like [`play_game`], also returning the record of the game.
+pub fn play_recorded_game(
+    a: &mut impl Agent,
+    b: &mut impl Agent,
+    board: Board,
+    a_is_black: bool,
+    index: usize,
+) -> (GameSummary, GameRecord) {
+    let record = if a_is_black {
+        simulate(board, Player::Black, a, b, &mut |_, _, _| {})
+    } else {
+        simulate(board, Player::Black, b, a, &mut |_, _, _| {})
+    };
+    let (black, white) = record.score();
+    let margin = black as i32 - white as i32;
+    let summary = GameSummary {
+        index,
+        a_is_black,
+        margin: if a_is_black { margin } else { -margin },
+        length: record.n_moves(),
+    };
+    (summary, record)
+}
+
+/// Plays `config.n_games` games between `a` and `b`, alternating colours, and calls `on_game`
+/// after each game with its summary and the totals so far.
+pub fn play_match(
+    a: &mut impl Agent,
+    b: &mut impl Agent,
+    config: &MatchConfig,
+    mut on_game: impl FnMut(&GameSummary, &MatchStats),
+) -> MatchStats {
+    let mut stats = MatchStats::default();
+    for index in 0..config.n_games {
+        let (board, a_is_black) = config.game(index);
+        let game = play_game(a, b, board, a_is_black, index);
+        stats.add(&game);
+        on_game(&game, &stats);
+    }
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{OneStepLookaheadAgent, RandomAgent};
+
+    #[test]
+    fn test_elo() {
+        assert_eq!(Elo::from_score(0.5), 0.0);
+        assert!((Elo::from_score(0.75) - 190.85).abs() < 0.01);
+        let stats = MatchStats { wins: 60, losses: 30, draws: 10, ..Default::default() };
+        let elo = stats.elo();
+        assert!(elo.lower < elo.diff && elo.diff < elo.upper);
+        assert!(elo.lower > 0.0);
+        let even = MatchStats { wins: 50, losses: 50, ..Default::default() };
+        assert_eq!(even.elo().diff, 0.0);
+        assert!(even.elo().lower < 0.0 && even.elo().upper > 0.0);
+    }
+
+    #[test]
+    fn test_openings() {
+        assert_eq!(openings_after(0), vec![Board::initial()]);
+        assert_eq!(openings_after(2).len(), 3);
+        assert_eq!(openings_after(4).len(), 60);
+        assert_eq!(parse_opening_plies("8"), Ok(8));
+        assert!(parse_opening_plies("7").is_err());
+        assert!(parse_opening_plies("x").is_err());
+        assert!(std::panic::catch_unwind(|| openings_after(7)).is_err());
+        assert!(opening_from_moves("F5D6").is_some());
+        assert_eq!(opening_from_moves("F5"), None);
+        assert_eq!(opening_from_moves("F5F5"), None);
+        assert_eq!(opening_from_moves("F5D6C3D3").map(|b| b.count(Player::Black) + b.count(Player::White)), Some(8));
+    }
+
+    #[test]
+    fn test_play_match() {
+        let openings = openings_after(2);
+        let config = MatchConfig::new(12).with_openings(openings.clone());
+        let mut seen = Vec::new();
+        let stats = play_match(&mut OneStepLookaheadAgent, &mut RandomAgent, &config, |game, stats| {
+            assert_eq!(stats.games() as usize, game.index + 1);
+            seen.push(*game);
+        });
+        assert_eq!(stats.games(), 12);
+        assert_eq!(seen.len(), 12);
+        assert!(seen.iter().all(|g| g.a_is_black == (g.index % 2 == 0)));
+        assert!(seen.iter().all(|g| (5..=60).contains(&g.length)));
+        assert_eq!(config.game(0).0, openings[0]);
+        assert_eq!(config.game(1).0, openings[0]);
+        assert_eq!(config.game(2).0, openings[1]);
+        assert_eq!(stats.total_margin, seen.iter().map(|g| g.margin as i64).sum::<i64>());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::agent::Agent;
+use crate::agent::{Agent, GameEnd};
 use crate::board::{Action, Board, Player, Pos};
 use rand::seq::IteratorRandom;
 // use mcts;
@@ -8,6 +8,7 @@ pub struct MCTSAgent {
     cursor: Rc<RefCell<MCTSNode>>,
     n_simulations: u32,
     exploration: f32,
+    stop: Arc<AtomicBool>,
 }
 impl MCTSAgent {
     pub fn new(n_simulations: u32, c: f32) -> Self {
@@ -18,28 +19,82 @@ impl MCTSAgent {
             exploration: c,
             cursor,
             tree: RefCell::new(tree),
+            stop: Arc::default(),
         }
     }
+    /// Moves the cursor to the node of (`board`, `p`) when the opponent's last move was not
+    /// reported through `opponent_move_callback`, starting a new tree if it cannot be found.
+    ///
+    /// Matches and tournaments start games from openings and reuse agents across games, so
+    /// `select_move` can no longer assume the cursor is already at the position it is asked
+    /// about.
+    fn sync_cursor(&mut self, board: &Board, p: Player) {
+        let (cursor_board, cursor_player) = {
+            let cursor = self.cursor.borrow();
+            (cursor.state.board, cursor.state.player)
+        };
+        if (cursor_board, cursor_player) == (*board, p) {
+            return;
+        }
+        if cursor_player == p.opponent() {
+            let moves: Vec<_> = cursor_board.valid_moves(cursor_player).collect();
+            let action = match moves.iter().find(|a| a.board == *board) {
+                Some(action) => Some(Some(*action)),
+                None if moves.is_empty() && cursor_board == *board => Some(None),
+                None => None,
+            };
+            if let Some(action) = action {
+                let new_cursor = self.tree.borrow().force_expand_on_action(&self.cursor, action);
+                if let Some(x) = new_cursor {
+                    self.cursor = x;
+                    return;
+                }
+            }
+        }
+        let tree = MCTS::new(*board, p);
+        self.cursor = tree.root.clone();
+        self.tree = RefCell::new(tree);
+    }
 }
 impl Agent for MCTSAgent {
+    fn set_stop(&mut self, stop: Arc<AtomicBool>) {
+        self.stop = stop;
+    }
     fn initialize(&mut self) {
         self.cursor = self.tree.borrow().root.clone();
     }
+    fn game_end(&mut self, _end: &GameEnd) {
+        // the tree of a finished game is of no use for the next one
+        let tree = MCTS::new(Board::initial(), Player::Black);
+        self.cursor = tree.root.clone();
+        self.tree = RefCell::new(tree);
+    }
     fn opponent_move_callback(&mut self, action: Option<Action>) {
-        let new_cursor = self.tree.borrow().force_expand_on_action(&self.cursor, action);
-        new_cursor.map_or_else(|| panic!("new_cursor is none"), |x| self.cursor = x);
-        eprintln!("root.n_visits = {}, cursor.n_visits={}", self.tree.borrow().root.borrow().n_visits.get(), self.cursor.borrow().n_visits.get());
-        eprintln!("N_NODES={}", unsafe { N_NODES });
+        // a move that does not continue the tree, e.g. in a game started from another
+        // position, is left to `sync_cursor` on the next `select_move`
+        let follows = {
+            let cursor = self.cursor.borrow();
+            let mut moves = cursor.state.board.valid_moves(cursor.state.player);
+            match action {
+                Some(action) => moves.any(|a| a == action),
+                None => moves.next().is_none() && !cursor.state.board.is_final(),
+            }
+        };
+        if follows {
+            let new_cursor = self.tree.borrow().force_expand_on_action(&self.cursor, action);
+            new_cursor.map_or_else(|| panic!("new_cursor is none"), |x| self.cursor = x);
+        }
     }
     // Monte Carlo Tree Search
     fn select_move(&mut self, board: &Board, p: Player) -> Option<Action> {
         // {
         //     eprintln!("{}", m.root.borrow());
         // }
+        self.sync_cursor(board, p);
         assert_eq!(self.cursor.borrow().state.player, p);
         assert_eq!(self.cursor.borrow().state.board, *board);
         // eprintln!("self.cursor = {}", self.cursor.borrow());
-        let best_node = self.tree.borrow_mut().best_action(&self.cursor, self.n_simulations, self.exploration);
+        let best_node = self.tree.borrow_mut().best_action(&self.cursor, self.n_simulations, self.exploration, &self.stop);
         best_node.map(|x| {
             // eprintln!("best_node = {}", x.borrow());
             assert_ne!(self.cursor.borrow().state.player, x.borrow().state.player);
@@ -66,6 +121,8 @@ use std::collections::VecDeque;
 use std::fmt::Display;
 use std::rc::{Rc, Weak};
 use std::cell::{Cell, RefCell};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 #[derive(Debug)]
 struct MCTSNode {
@@ -86,7 +143,6 @@ impl std::fmt::Display for MCTSNode {
         Ok(())
     }
 }
-static mut N_NODES: u64 = 0;
 
 impl MCTSNode {
     fn new_root(board: Board, player: Player) -> Self {
@@ -115,9 +171,6 @@ impl MCTSNode {
     }
 
     fn new(state: OthelloState, parent: &Rc<RefCell<MCTSNode>>, causing_action: Option<Action>) -> Self {
-        unsafe {
-            N_NODES += 1;
-        }
         assert_ne!(state.player, parent.borrow().state.player);
         assert_eq!(
             parent.borrow().untried_actions.is_none(), causing_action.is_none(), "parent=\n{:#?}, causing_action={:?}", parent, causing_action);
@@ -189,7 +242,7 @@ impl MCTSNode {
     fn rollout_policy(state: &OthelloState) -> Option<Action> {
         let valid_moves_mask = state.board.valid_moves_fast(state.player);
         for p in [Pos::A1, Pos::A8, Pos::H1, Pos::H8] {
-            if valid_moves_mask & (1u64 << p as u64) != 0 {
+            if valid_moves_mask.contains(p) {
                 return Some(Action {
                     at: p,
                     board: state.board.place_at_unchecked(state.player, p)
@@ -260,9 +313,13 @@ impl MCTS {
         }
     }
 
-    fn best_action(&mut self, start: &Rc<RefCell<MCTSNode>>, n_simulations: u32, c: f32) -> Option<Rc<RefCell<MCTSNode>>>{
+    /// Runs `n_simulations` simulations from *start*, fewer if `stop` is raised meanwhile.
+    fn best_action(&mut self, start: &Rc<RefCell<MCTSNode>>, n_simulations: u32, c: f32, stop: &AtomicBool) -> Option<Rc<RefCell<MCTSNode>>>{
         // eprintln!("simulating (q={}, n={}) from board = {:?}", self.root.borrow().q(), self.root.borrow().n(), self.root.borrow().state.board);
         for _i in 0..n_simulations {
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
             if let Some(v) = self.tree_policy(start, c) {
                 let reward = v.borrow().rollout();
                 self.backpropagate(&v, reward);
@@ -411,3 +468,52 @@ fn uct(vi: &MCTSNode, v: &MCTSNode, c: f32) -> f32 {
     assert_ne!(vi.state.player, v.state.player);
     vi.q() as f32 / vi.n() as f32 + c * ((v.n() as f32).ln() / (vi.n() as f32)).sqrt()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::RandomAgent;
+
+    #[test]
+    fn test_self_play() {
+        let mut black = MCTSAgent::new(50, 1.4);
+        let mut white = MCTSAgent::new(50, 1.4);
+        let record = crate::simulate::simulate(Board::initial(), Player::Black, &mut black, &mut white, &mut |_, _, _| {});
+        assert!(record.final_board.is_final());
+        // a second game from another position, reusing the same agents
+        let opening = crate::match_runner::openings_after(4)[7];
+        let record = crate::simulate::simulate(opening, Player::Black, &mut white, &mut black, &mut |_, _, _| {});
+        assert!(record.final_board.is_final());
+        assert!(record.n_moves() <= 56);
+    }
+
+    #[test]
+    fn test_stop() {
+        let mut agent = MCTSAgent::new(u32::MAX, 1.4);
+        let stop = Arc::new(AtomicBool::new(true));
+        agent.set_stop(stop);
+        // returns at once, without a simulation to choose from
+        assert_eq!(agent.select_move(&Board::initial(), Player::Black), None);
+    }
+
+    #[test]
+    fn test_select_move_without_callbacks() {
+        // without opponent_move_callback the agent has to find the opponent's moves itself
+        let mut agent = MCTSAgent::new(50, 1.4);
+        let mut board = Board::initial();
+        let mut p = Player::Black;
+        while !board.is_final() {
+            let action = match p {
+                Player::Black => agent.select_move(&board, p),
+                Player::White => RandomAgent.select_move(&board, p),
+            };
+            if let Some(action) = action {
+                board = action.board;
+            }
+            p.flip();
+        }
+        let start = Board::initial().valid_moves(Player::Black).next().unwrap().board;
+        let action = agent.select_move(&start, Player::White).unwrap();
+        assert!(start.valid_moves(Player::White).any(|a| a == action));
+    }
+}
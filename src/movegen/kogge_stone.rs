@@ -0,0 +1,65 @@
+//! Kogge-Stone parallel prefix fills: three shift steps (1, 2, 4) per direction instead of six.
+use crate::board::Pos;
+
+pub const NAME: &str = "kogge-stone";
+
+const NOT_A_FILE: u64 = 0x7f7f_7f7f_7f7f_7f7f;
+const NOT_H_FILE: u64 = 0xfefe_fefe_fefe_fefe;
+
+/// Shift amount (positive is left) and the squares a disk may land on after one step.
+pub(super) const DIRECTIONS: [(i32, u64); 8] = [
+    (8, u64::MAX),     // North
+    (-8, u64::MAX),    // South
+    (-1, NOT_A_FILE),  // East
+    (1, NOT_H_FILE),   // West
+    (9, NOT_H_FILE),   // Northwest
+    (-9, NOT_A_FILE),  // Southeast
+    (7, NOT_A_FILE),   // Northeast
+    (-7, NOT_H_FILE),  // Southwest
+];
+
+#[inline(always)]
+fn sh(x: u64, s: i32) -> u64 {
+    if s > 0 {
+        x << s
+    } else {
+        x >> -s
+    }
+}
+
+/// Occluded fill of `gen` through `pro`, which must already be masked for the direction.
+#[inline(always)]
+fn fill(gen: u64, pro: u64, s: i32) -> u64 {
+    let mut g = gen;
+    let mut p = pro;
+    g |= p & sh(g, s);
+    p &= sh(p, s);
+    g |= p & sh(g, 2 * s);
+    p &= sh(p, 2 * s);
+    g | (p & sh(g, 4 * s))
+}
+
+pub fn moves(my_disks: u64, opp_disks: u64) -> u64 {
+    let empty = !(my_disks | opp_disks);
+    let mut moves = 0u64;
+    for (s, m) in DIRECTIONS {
+        let run = fill(my_disks, opp_disks & m, s) & opp_disks;
+        moves |= sh(run, s) & m;
+    }
+    moves & empty
+}
+
+pub fn flips(my_disks: u64, opp_disks: u64, pos: Pos) -> u64 {
+    let i = pos.mask();
+    if (my_disks | opp_disks) & i != 0 {
+        return 0;
+    }
+    let mut captured_disks = 0u64;
+    for (s, m) in DIRECTIONS {
+        let run = fill(i, opp_disks & m, s) & opp_disks;
+        if sh(run, s) & m & my_disks != 0 {
+            captured_disks |= run;
+        }
+    }
+    captured_disks
+}
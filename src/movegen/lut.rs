@@ -0,0 +1,94 @@
+//! Per-direction ray lookup tables: the first non-opponent square on each ray decides the flips.
+use crate::board::Pos;
+
+pub const NAME: &str = "lut";
+
+/// (row step, column step) of each direction.
+const STEPS: [(i32, i32); 8] = [(-1, 0), (1, 0), (0, 1), (0, -1), (-1, -1), (1, 1), (-1, 1), (1, -1)];
+
+const fn build_rays() -> [[u64; 64]; 8] {
+    let mut rays = [[0u64; 64]; 8];
+    let mut d = 0;
+    while d < 8 {
+        let (dr, dc) = STEPS[d];
+        let mut i = 0;
+        while i < 64 {
+            let mut r = (63 - i as i32) / 8 + dr;
+            let mut c = (63 - i as i32) % 8 + dc;
+            while r >= 0 && r < 8 && c >= 0 && c < 8 {
+                rays[d][i] |= 1 << (63 - (r * 8 + c));
+                r += dr;
+                c += dc;
+            }
+            i += 1;
+        }
+        d += 1;
+    }
+    rays
+}
+
+const fn build_neighbours() -> [u64; 64] {
+    let mut neighbours = [0u64; 64];
+    let mut i = 0;
+    while i < 64 {
+        let mut d = 0;
+        while d < 8 {
+            let (dr, dc) = STEPS[d];
+            let r = (63 - i as i32) / 8 + dr;
+            let c = (63 - i as i32) % 8 + dc;
+            if r >= 0 && r < 8 && c >= 0 && c < 8 {
+                neighbours[i] |= 1 << (63 - (r * 8 + c));
+            }
+            d += 1;
+        }
+        i += 1;
+    }
+    neighbours
+}
+
+static RAYS: [[u64; 64]; 8] = build_rays();
+static NEIGHBOURS: [u64; 64] = build_neighbours();
+
+pub fn moves(my_disks: u64, opp_disks: u64) -> u64 {
+    let mut candidates = !(my_disks | opp_disks);
+    let mut moves = 0u64;
+    while candidates != 0 {
+        let i = candidates.trailing_zeros() as usize;
+        candidates &= candidates - 1;
+        if NEIGHBOURS[i] & opp_disks != 0 && ray_flips(my_disks, opp_disks, i) != 0 {
+            moves |= 1 << i;
+        }
+    }
+    moves
+}
+
+pub fn flips(my_disks: u64, opp_disks: u64, pos: Pos) -> u64 {
+    if (my_disks | opp_disks) & pos.mask() != 0 {
+        return 0;
+    }
+    ray_flips(my_disks, opp_disks, pos.index() as usize)
+}
+
+#[inline]
+fn ray_flips(my_disks: u64, opp_disks: u64, i: usize) -> u64 {
+    let mut captured_disks = 0u64;
+    for (d, &(dr, dc)) in STEPS.iter().enumerate() {
+        let ray = RAYS[d][i];
+        let blockers = ray & !opp_disks;
+        if blockers == 0 {
+            continue;
+        }
+        // bit indices grow towards A1, i.e. when the step decreases the row-major index
+        let (first, between) = if dr * 8 + dc < 0 {
+            let first = blockers & blockers.wrapping_neg();
+            (first, ray & (first - 1))
+        } else {
+            let lz = blockers.leading_zeros();
+            (1u64 << (63 - lz), ray & !(u64::MAX >> lz))
+        };
+        if first & my_disks != 0 {
+            captured_disks |= between;
+        }
+    }
+    captured_disks
+}
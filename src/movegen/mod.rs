@@ -0,0 +1,116 @@
+//! Move generation kernels on raw bitboards.
+//!
+//! Every backend exposes the same two functions, `moves(my_disks, opp_disks)` returning the
+//! mask of legal moves and `flips(my_disks, opp_disks, pos)` returning the disks flipped by
+//! playing at `pos` (empty if the move is illegal). All backends are always compiled so they
+//! can be benchmarked against each other; the one used by [`Board`](crate::board::Board) is
+//! chosen with the `movegen-simd`, `movegen-kogge-stone` or `movegen-lut` cargo features, in
+//! that order of precedence, falling back to [`shift`]. [`simd`] only uses vector instructions
+//! when AVX2 is enabled at compile time, and its [`NAME`](simd::NAME) tells which build it is.
+use crate::board::Pos;
+
+pub mod kogge_stone;
+pub mod lut;
+pub mod shift;
+pub mod simd;
+
+#[cfg(feature = "movegen-simd")]
+use simd as selected;
+#[cfg(all(feature = "movegen-kogge-stone", not(feature = "movegen-simd")))]
+use kogge_stone as selected;
+#[cfg(all(
+    feature = "movegen-lut",
+    not(any(feature = "movegen-simd", feature = "movegen-kogge-stone"))
+))]
+use lut as selected;
+#[cfg(not(any(
+    feature = "movegen-simd",
+    feature = "movegen-kogge-stone",
+    feature = "movegen-lut"
+)))]
+use shift as selected;
+
+/// Name of the backend selected at compile time.
+pub const BACKEND: &str = selected::NAME;
+
+#[inline]
+pub fn moves(my_disks: u64, opp_disks: u64) -> u64 {
+    selected::moves(my_disks, opp_disks)
+}
+
+#[inline]
+pub fn flips(my_disks: u64, opp_disks: u64, pos: Pos) -> u64 {
+    selected::flips(my_disks, opp_disks, pos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{Board, Player};
+    use rand::seq::IteratorRandom;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type Backend = (&'static str, fn(u64, u64) -> u64, fn(u64, u64, Pos) -> u64);
+    const BACKENDS: [Backend; 4] = [
+        (shift::NAME, shift::moves, shift::flips),
+        (kogge_stone::NAME, kogge_stone::moves, kogge_stone::flips),
+        (lut::NAME, lut::moves, lut::flips),
+        (simd::NAME, simd::moves, simd::flips),
+    ];
+
+    fn check(board: &Board, p: Player) {
+        let my_disks = board.get_disks(p).bits();
+        let opp_disks = board.get_disks(p.opponent()).bits();
+        let expected: Vec<_> = board.valid_moves_slow(p).collect();
+        let expected_moves = expected.iter().fold(0, |acc, a| acc | a.at.mask());
+        for (name, moves, flips) in BACKENDS {
+            assert_eq!(moves(my_disks, opp_disks), expected_moves, "{} moves, board={:?}", name, board);
+            for &pos in Pos::iter() {
+                let f = flips(my_disks, opp_disks, pos);
+                match expected.iter().find(|a| a.at == pos) {
+                    Some(a) => assert_eq!(
+                        f,
+                        opp_disks & !a.board.get_disks(p.opponent()).bits(),
+                        "{} flips at {}, board={:?}",
+                        name,
+                        pos,
+                        board
+                    ),
+                    None if board.get(&pos).is_none() => {
+                        assert_eq!(f, 0, "{} flips at {}, board={:?}", name, pos, board)
+                    }
+                    None => (),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_backends_agree_with_valid_moves_slow() {
+        let mut rng = StdRng::seed_from_u64(29);
+        for _ in 0..200 {
+            let mut board = Board::initial();
+            let mut p = Player::Black;
+            while !board.is_final() {
+                check(&board, p);
+                if let Some(action) = board.valid_moves_slow(p).choose(&mut rng) {
+                    board = action.board;
+                }
+                p.flip();
+            }
+        }
+    }
+
+    #[test]
+    fn test_backends_on_random_boards() {
+        use rand::Rng;
+        let mut rng = StdRng::seed_from_u64(2029);
+        for _ in 0..2000 {
+            let occupied: u64 = rng.gen();
+            let black: u64 = rng.gen::<u64>() & occupied;
+            let board = Board::from_disks(black.into(), (occupied & !black).into());
+            check(&board, Player::Black);
+            check(&board, Player::White);
+        }
+    }
+}
@@ -0,0 +1,39 @@
+//! The reference backend: six masked shifts per direction, one direction at a time.
+use crate::board::{CompassRose, Pos, Shift};
+
+pub const NAME: &str = "shift";
+
+pub fn moves(my_disks: u64, opp_disks: u64) -> u64 {
+    let empty = !(my_disks | opp_disks);
+    let mut moves = 0u64;
+    for d in CompassRose::iter() {
+        let x = my_disks.shift(d) & opp_disks;
+        let x = x | (x.shift(d) & opp_disks);
+        let x = x | (x.shift(d) & opp_disks);
+        let x = x | (x.shift(d) & opp_disks);
+        let x = x | (x.shift(d) & opp_disks);
+        let x = x | (x.shift(d) & opp_disks);
+        moves |= x.shift(d) & empty;
+    }
+    moves
+}
+
+pub fn flips(my_disks: u64, opp_disks: u64, pos: Pos) -> u64 {
+    let i = pos.mask();
+    if (my_disks | opp_disks) & i != 0 {
+        return 0;
+    }
+    let mut captured_disks = 0u64;
+    for d in CompassRose::iter() {
+        let x = i.shift(d) & opp_disks;
+        let x = x | (x.shift(d) & opp_disks);
+        let x = x | (x.shift(d) & opp_disks);
+        let x = x | (x.shift(d) & opp_disks);
+        let x = x | (x.shift(d) & opp_disks);
+        let x = x | (x.shift(d) & opp_disks);
+        if x.shift(d) & my_disks != 0 {
+            captured_disks |= x; 
+        }
+    }
+    captured_disks
+}
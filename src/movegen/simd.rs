@@ -0,0 +1,188 @@
+//! Kogge-Stone fills over four directions at once.
+//!
+//! On x86_64 with AVX2 enabled at compile time (`-C target-cpu=native` or
+//! `-C target-feature=+avx2`) the four directions are the lanes of one 256-bit register, shifted
+//! with `_mm256_sllv_epi64`/`_mm256_srlv_epi64`. Elsewhere the same algorithm runs on plain
+//! `[u64; 4]` arrays and [`NAME`] says so, so benchmarks never report it as SIMD.
+use crate::board::Pos;
+
+#[cfg(all(target_arch = "x86_64", target_feature = "avx2"))]
+pub use avx2::{flips, moves, NAME};
+#[cfg(not(all(target_arch = "x86_64", target_feature = "avx2")))]
+pub use portable::{flips, moves, NAME};
+
+const NOT_A_FILE: u64 = 0x7f7f_7f7f_7f7f_7f7f;
+const NOT_H_FILE: u64 = 0xfefe_fefe_fefe_fefe;
+
+/// West, North, Northwest and Northeast when shifting left; East, South, Southeast and
+/// Southwest when shifting right.
+const SHIFTS: [u64; 4] = [1, 8, 9, 7];
+const LEFT_MASKS: [u64; 4] = [NOT_H_FILE, u64::MAX, NOT_H_FILE, NOT_A_FILE];
+const RIGHT_MASKS: [u64; 4] = [NOT_A_FILE, u64::MAX, NOT_A_FILE, NOT_H_FILE];
+
+#[cfg(all(target_arch = "x86_64", target_feature = "avx2"))]
+mod avx2 {
+    use super::{Pos, LEFT_MASKS, RIGHT_MASKS, SHIFTS};
+    use std::arch::x86_64::*;
+
+    pub const NAME: &str = "simd-avx2";
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    fn lanes(x: [u64; 4]) -> __m256i {
+        _mm256_set_epi64x(x[3] as i64, x[2] as i64, x[1] as i64, x[0] as i64)
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    fn splat(x: u64) -> __m256i {
+        _mm256_set1_epi64x(x as i64)
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    fn or_lanes(x: __m256i) -> u64 {
+        let (a, b) = (_mm256_extract_epi64::<0>(x), _mm256_extract_epi64::<1>(x));
+        let (c, d) = (_mm256_extract_epi64::<2>(x), _mm256_extract_epi64::<3>(x));
+        (a | b | c | d) as u64
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    fn fill_left(gen: u64, pro: __m256i) -> __m256i {
+        let (mut g, mut p) = (splat(gen), pro);
+        for k in [1, 2] {
+            let s = lanes(SHIFTS.map(|s| s * k));
+            g = _mm256_or_si256(g, _mm256_and_si256(p, _mm256_sllv_epi64(g, s)));
+            p = _mm256_and_si256(p, _mm256_sllv_epi64(p, s));
+        }
+        let s = lanes(SHIFTS.map(|s| s * 4));
+        _mm256_or_si256(g, _mm256_and_si256(p, _mm256_sllv_epi64(g, s)))
+    }
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    fn fill_right(gen: u64, pro: __m256i) -> __m256i {
+        let (mut g, mut p) = (splat(gen), pro);
+        for k in [1, 2] {
+            let s = lanes(SHIFTS.map(|s| s * k));
+            g = _mm256_or_si256(g, _mm256_and_si256(p, _mm256_srlv_epi64(g, s)));
+            p = _mm256_and_si256(p, _mm256_srlv_epi64(p, s));
+        }
+        let s = lanes(SHIFTS.map(|s| s * 4));
+        _mm256_or_si256(g, _mm256_and_si256(p, _mm256_srlv_epi64(g, s)))
+    }
+
+    /// Runs of opponent disks next to `gen` in every direction.
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    fn runs(gen: u64, opp_disks: u64) -> (__m256i, __m256i) {
+        let opp = splat(opp_disks);
+        let left = fill_left(gen, _mm256_and_si256(opp, lanes(LEFT_MASKS)));
+        let right = fill_right(gen, _mm256_and_si256(opp, lanes(RIGHT_MASKS)));
+        (_mm256_and_si256(left, opp), _mm256_and_si256(right, opp))
+    }
+
+    pub fn moves(my_disks: u64, opp_disks: u64) -> u64 {
+        // SAFETY: this module is only compiled with AVX2 enabled
+        unsafe { moves_avx2(my_disks, opp_disks) }
+    }
+
+    pub fn flips(my_disks: u64, opp_disks: u64, pos: Pos) -> u64 {
+        // SAFETY: as in `moves`
+        unsafe { flips_avx2(my_disks, opp_disks, pos) }
+    }
+
+    #[target_feature(enable = "avx2")]
+    fn moves_avx2(my_disks: u64, opp_disks: u64) -> u64 {
+        let (left, right) = runs(my_disks, opp_disks);
+        let shifts = lanes(SHIFTS);
+        let left = _mm256_and_si256(_mm256_sllv_epi64(left, shifts), lanes(LEFT_MASKS));
+        let right = _mm256_and_si256(_mm256_srlv_epi64(right, shifts), lanes(RIGHT_MASKS));
+        or_lanes(_mm256_or_si256(left, right)) & !(my_disks | opp_disks)
+    }
+
+    #[target_feature(enable = "avx2")]
+    fn flips_avx2(my_disks: u64, opp_disks: u64, pos: Pos) -> u64 {
+        let i = pos.mask();
+        if (my_disks | opp_disks) & i != 0 {
+            return 0;
+        }
+        let (left, right) = runs(i, opp_disks);
+        let (shifts, my, zero) = (lanes(SHIFTS), splat(my_disks), _mm256_setzero_si256());
+        // a run is captured if one of my disks closes it
+        let left_end = _mm256_and_si256(_mm256_and_si256(_mm256_sllv_epi64(left, shifts), lanes(LEFT_MASKS)), my);
+        let right_end = _mm256_and_si256(_mm256_and_si256(_mm256_srlv_epi64(right, shifts), lanes(RIGHT_MASKS)), my);
+        let left = _mm256_andnot_si256(_mm256_cmpeq_epi64(left_end, zero), left);
+        let right = _mm256_andnot_si256(_mm256_cmpeq_epi64(right_end, zero), right);
+        or_lanes(_mm256_or_si256(left, right))
+    }
+}
+
+/// The same lanes as plain arrays, for targets without AVX2.
+#[cfg_attr(all(target_arch = "x86_64", target_feature = "avx2"), allow(dead_code))]
+mod portable {
+    use super::{Pos, LEFT_MASKS, RIGHT_MASKS, SHIFTS};
+
+    pub const NAME: &str = "simd-portable";
+
+    type Lanes = [u64; 4];
+
+    #[inline(always)]
+    fn lanes(f: impl FnMut(usize) -> u64) -> Lanes {
+        std::array::from_fn(f)
+    }
+
+    #[inline(always)]
+    fn fill_left(gen: u64, pro: Lanes) -> Lanes {
+        let mut g = [gen; 4];
+        let mut p = pro;
+        for k in [1, 2] {
+            g = lanes(|i| g[i] | (p[i] & (g[i] << (SHIFTS[i] * k))));
+            p = lanes(|i| p[i] & (p[i] << (SHIFTS[i] * k)));
+        }
+        lanes(|i| g[i] | (p[i] & (g[i] << (SHIFTS[i] * 4))))
+    }
+
+    #[inline(always)]
+    fn fill_right(gen: u64, pro: Lanes) -> Lanes {
+        let mut g = [gen; 4];
+        let mut p = pro;
+        for k in [1, 2] {
+            g = lanes(|i| g[i] | (p[i] & (g[i] >> (SHIFTS[i] * k))));
+            p = lanes(|i| p[i] & (p[i] >> (SHIFTS[i] * k)));
+        }
+        lanes(|i| g[i] | (p[i] & (g[i] >> (SHIFTS[i] * 4))))
+    }
+
+    #[inline(always)]
+    fn runs(gen: u64, opp_disks: u64) -> (Lanes, Lanes) {
+        let left = fill_left(gen, LEFT_MASKS.map(|m| opp_disks & m));
+        let right = fill_right(gen, RIGHT_MASKS.map(|m| opp_disks & m));
+        (left.map(|x| x & opp_disks), right.map(|x| x & opp_disks))
+    }
+
+    pub fn moves(my_disks: u64, opp_disks: u64) -> u64 {
+        let (left, right) = runs(my_disks, opp_disks);
+        let left = lanes(|i| (left[i] << SHIFTS[i]) & LEFT_MASKS[i]);
+        let right = lanes(|i| (right[i] >> SHIFTS[i]) & RIGHT_MASKS[i]);
+        let moves = lanes(|i| left[i] | right[i]);
+        (moves[0] | moves[1] | moves[2] | moves[3]) & !(my_disks | opp_disks)
+    }
+
+    pub fn flips(my_disks: u64, opp_disks: u64, pos: Pos) -> u64 {
+        let i = pos.mask();
+        if (my_disks | opp_disks) & i != 0 {
+            return 0;
+        }
+        let (left, right) = runs(i, opp_disks);
+        let left = lanes(|k| {
+            if (left[k] << SHIFTS[k]) & LEFT_MASKS[k] & my_disks != 0 { left[k] } else { 0 }
+        });
+        let right = lanes(|k| {
+            if (right[k] >> SHIFTS[k]) & RIGHT_MASKS[k] & my_disks != 0 { right[k] } else { 0 }
+        });
+        let captured = lanes(|k| left[k] | right[k]);
+        captured[0] | captured[1] | captured[2] | captured[3]
+    }
+}
@@ -0,0 +1,101 @@
+use crate::board::{Board, Player, Pos};
+
+/// Leaf counts from the initial position with Black to move, indexed by depth.
+///
+/// A pass counts as a ply and a finished game counts as a single leaf, whatever depth is left.
+pub const INITIAL_PERFT: [u64; 15] = [
+    1,
+    4,
+    12,
+    56,
+    244,
+    1396,
+    8200,
+    55092,
+    390216,
+    3005288,
+    24571284,
+    212258800,
+    1939886636,
+    18429641748,
+    184042084512,
+];
+
+/// Number of leaf nodes of the game tree of depth `depth` rooted at `board` with `p` to move.
+pub fn perft(board: &Board, p: Player, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = board.moves(p);
+    if moves.is_empty() {
+        if board.moves(p.opponent()).is_empty() {
+            return 1; // game over
+        }
+        return perft(board, p.opponent(), depth - 1);
+    }
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    let mut board = *board;
+    let mut nodes = 0;
+    for pos in moves {
+        let mv = board.make_move(p, pos).unwrap();
+        nodes += perft(&board, p.opponent(), depth - 1);
+        board.unmake_move(p, mv);
+    }
+    nodes
+}
+
+/// [`perft`] split by first move, `None` standing for a pass.
+///
+/// Empty if the game is already over.
+pub fn divide(board: &Board, p: Player, depth: u32) -> Vec<(Option<Pos>, u64)> {
+    if depth == 0 || board.is_final() {
+        return Vec::new();
+    }
+    let moves = board.moves(p);
+    if moves.is_empty() {
+        return vec![(None, perft(board, p.opponent(), depth - 1))];
+    }
+    moves
+        .map(|pos| {
+            let mut board = *board;
+            board.make_move(p, pos).unwrap();
+            (Some(pos), perft(&board, p.opponent(), depth - 1))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_perft_initial() {
+        for (depth, &expected) in INITIAL_PERFT.iter().enumerate().take(10) {
+            assert_eq!(perft(&Board::initial(), Player::Black, depth as u32), expected, "depth={}", depth);
+        }
+    }
+    #[test]
+    fn test_divide() {
+        let board = Board::initial();
+        let div = divide(&board, Player::Black, 9);
+        assert_eq!(div.len(), 4);
+        // the four first moves are symmetric
+        assert!(div.iter().all(|&(_, n)| n == INITIAL_PERFT[9] / 4));
+        assert_eq!(div.iter().map(|&(_, n)| n).sum::<u64>(), INITIAL_PERFT[9]);
+    }
+    #[test]
+    fn test_perft_pass_and_game_over() {
+        use crate::board::SquareSet;
+        // White has no disk left: the game is over and counts as one leaf at any depth
+        let board = Board::from_disks(SquareSet::from(Pos::D4) | Pos::E5, SquareSet::EMPTY);
+        assert_eq!(perft(&board, Player::Black, 5), 1);
+        assert!(divide(&board, Player::Black, 5).is_empty());
+        // Black on B1..G1 must pass; White on A1 then has the single move H1
+        let black: SquareSet = [Pos::B1, Pos::C1, Pos::D1, Pos::E1, Pos::F1, Pos::G1].into_iter().collect();
+        let board = Board::from_disks(black, Pos::A1.into());
+        assert!(board.moves(Player::Black).is_empty());
+        assert_eq!(divide(&board, Player::Black, 2), vec![(None, 1)]);
+        assert_eq!(perft(&board, Player::Black, 3), 1);
+    }
+}
@@ -0,0 +1,178 @@
+use clap::{Parser, ValueEnum};
+use reversi::agent::Agent;
+use reversi::board::{Action, Board, Player, Pos, SquareSet};
+use reversi::registry::AgentSpec;
+use reversi::render::{legal_moves, symbol, View};
+use std::io::{BufRead, IsTerminal, Write};
+
+#[derive(Parser, Debug)]
+#[command(about = "Play Reversi against the engine in the terminal")]
+struct Cli {
+    #[arg(
+        long,
+        default_value = "alphabeta:depth=4",
+        help = "engine opponent and its strength, e.g. random, alphabeta:depth=6 or mcts:sims=20000"
+    )]
+    agent: AgentSpec,
+    #[arg(long, value_enum, default_value_t = Side::Black, help = "color you play")]
+    side: Side,
+    #[arg(long, default_value_t = false, help = "do not mark legal moves")]
+    no_hints: bool,
+    #[arg(long, default_value_t = false, help = "no ANSI colors [default: colors on terminals, unless NO_COLOR is set]")]
+    no_color: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Side {
+    Black,
+    White,
+}
+
+/// A position of the game with the move that led to it.
+#[derive(Clone, Copy)]
+struct State {
+    board: Board,
+    to_move: Player,
+    last_move: Option<(Player, Option<Pos>, SquareSet)>,
+}
+
+impl State {
+    fn after(&self, action: Option<Action>) -> State {
+        let p = self.to_move;
+        State {
+            board: action.map_or(self.board, |a| a.board),
+            to_move: p.opponent(),
+            last_move: Some((
+                p,
+                action.map(|a| a.at),
+                action.map_or(SquareSet::default(), |a| self.board.flips(p, a.at)),
+            )),
+        }
+    }
+}
+
+enum Input {
+    Move(Pos),
+    Pass,
+    Undo,
+    Hint,
+    New,
+    Quit,
+}
+
+fn parse_input(line: &str) -> Option<Input> {
+    match line.trim().to_ascii_lowercase().as_str() {
+        "pass" => Some(Input::Pass),
+        "undo" | "u" => Some(Input::Undo),
+        "hint" | "h" => Some(Input::Hint),
+        "new" => Some(Input::New),
+        "quit" | "q" | "exit" => Some(Input::Quit),
+        s => Pos::try_from(s).ok().map(Input::Move),
+    }
+}
+
+fn main() -> std::io::Result<()> {
+    let cli = Cli::parse();
+    let human = match cli.side {
+        Side::Black => Player::Black,
+        Side::White => Player::White,
+    };
+    let color = !cli.no_color && std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    let mut engine = cli.agent.build();
+    let initial = State {
+        board: Board::initial(),
+        to_move: Player::Black,
+        last_move: None,
+    };
+    let mut history = vec![initial];
+    let mut lines = std::io::stdin().lock().lines();
+    println!("You play {} against {}. Moves like d3; also pass, undo, hint, new, quit.", symbol(human), cli.agent);
+    engine.initialize();
+    // whether the board has changed since it was last shown
+    let mut changed = true;
+    loop {
+        let state = *history.last().expect("the initial state is never undone");
+        let mut view = View::new(state.board).color(color);
+        if let Some((p, pos, flips)) = state.last_move {
+            view = view.last_move(p, pos, flips);
+        }
+        if state.board.is_final() {
+            if changed {
+                let (mine, theirs) = (state.board.count(human), state.board.count(human.opponent()));
+                let verdict = match mine.cmp(&theirs) {
+                    std::cmp::Ordering::Greater => "You win",
+                    std::cmp::Ordering::Less => "You lose",
+                    std::cmp::Ordering::Equal => "Draw",
+                };
+                println!("\n{}\n{} {}-{}. undo, new or quit?", view, verdict, mine, theirs);
+            }
+        } else if state.to_move != human {
+            let action = engine.select_move(&state.board, state.to_move);
+            history.push(state.after(action));
+            continue;
+        } else if state.board.valid_moves_fast(human).is_empty() {
+            println!("\n{}\nYou have no legal move and pass", view);
+            engine.opponent_move_callback(None);
+            history.push(state.after(None));
+            continue;
+        } else {
+            if !cli.no_hints {
+                view = view.hints(human);
+            }
+            if changed {
+                println!("\n{}", view);
+            }
+            print!("> ");
+            std::io::stdout().flush()?;
+        }
+        let Some(line) = lines.next().transpose()? else {
+            return Ok(());
+        };
+        let len = history.len();
+        let moves: Vec<Action> = state.board.valid_moves(human).collect();
+        let playing = !state.board.is_final() && state.to_move == human;
+        match parse_input(&line) {
+            Some(Input::Move(pos)) if playing => match moves.iter().find(|a| a.at == pos) {
+                Some(&action) => {
+                    engine.opponent_move_callback(Some(action));
+                    history.push(state.after(Some(action)));
+                }
+                None => println!("{} is not a legal move; legal: {}", pos, legal_moves(&state.board, human)),
+            },
+            Some(Input::Pass) if playing => {
+                if moves.is_empty() {
+                    engine.opponent_move_callback(None);
+                    history.push(state.after(None));
+                } else {
+                    println!("You can only pass without a legal move");
+                }
+            }
+            Some(Input::Hint) if playing => match engine.select_move(&state.board, human) {
+                Some(action) => println!("The engine would play {}", action.at),
+                None => println!("The engine would pass"),
+            },
+            Some(Input::Undo) => {
+                // back to the previous position where we had a move, or to the start
+                let before = history.len();
+                while history.len() > 1 {
+                    history.pop();
+                    let last = history.last().expect("at least one state left");
+                    if last.to_move == human && !last.board.valid_moves_fast(human).is_empty() {
+                        break;
+                    }
+                }
+                if history.len() == before {
+                    println!("Nothing to undo");
+                }
+            }
+            Some(Input::New) => {
+                history.truncate(1);
+                engine.initialize();
+            }
+            Some(Input::Quit) => return Ok(()),
+            Some(_) => println!("The game is over: undo, new or quit"),
+            None => println!("Unknown input {:?}: a move like d3, or pass, undo, hint, new, quit", line.trim()),
+        }
+        changed = history.len() != len;
+    }
+}
@@ -0,0 +1,210 @@
+//! Agents selected by spec strings such as `random`, `lookahead`, `mcts:sims=20000,c=1.2`,
+//! `alphabeta:depth=6` or `human`, so binaries can pick agents from the command line or config
+//! files.
+use crate::agent::{Agent, OneStepLookaheadAgent, RandomAgent};
+use crate::dfs_agent::DfsAgent;
+use crate::human_agent::HumanAgent;
+use crate::mcts_agent::MCTSAgent;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// A parsed and validated agent spec, cheap to clone and send across threads.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AgentSpec {
+    Random,
+    Lookahead,
+    Mcts { n_simulations: u32, c: f32 },
+    AlphaBeta { depth: usize },
+    /// Moves typed in on stdin, see [`HumanAgent::stdin`].
+    Human,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SpecError {
+    #[error("unknown agent {0:?}, expected one of random, lookahead, mcts, alphabeta, human")]
+    UnknownAgent(String),
+    #[error("expected key=value, got {0:?}")]
+    MalformedParameter(String),
+    #[error("{agent} has no parameter {key:?}")]
+    UnknownParameter { agent: String, key: String },
+    #[error("parameter {0:?} given twice")]
+    DuplicateParameter(String),
+    #[error("invalid value {value:?} for {key}: {reason}")]
+    InvalidValue {
+        key: String,
+        value: String,
+        reason: String,
+    },
+    #[error("line {line}: {source}")]
+    Line {
+        line: usize,
+        #[source]
+        source: Box<SpecError>,
+    },
+}
+
+impl AgentSpec {
+    pub const DEFAULT_SIMULATIONS: u32 = 10000;
+    pub const DEFAULT_C: f32 = 1.4;
+    pub const DEFAULT_DEPTH: usize = 4;
+    pub const MAX_DEPTH: usize = 20;
+
+    pub fn build(&self) -> Box<dyn Agent> {
+        match *self {
+            AgentSpec::Random => Box::new(RandomAgent),
+            AgentSpec::Lookahead => Box::new(OneStepLookaheadAgent),
+            AgentSpec::Mcts { n_simulations, c } => Box::new(MCTSAgent::new(n_simulations, c)),
+            AgentSpec::AlphaBeta { depth } => Box::new(DfsAgent::new(depth)),
+            AgentSpec::Human => Box::new(HumanAgent::stdin()),
+        }
+    }
+}
+
+fn invalid(key: &str, value: &str, reason: impl ToString) -> SpecError {
+    SpecError::InvalidValue {
+        key: key.to_string(),
+        value: value.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+impl FromStr for AgentSpec {
+    type Err = SpecError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (name, rest) = s.split_once(':').unwrap_or((s, ""));
+        let params_of = |allowed: &[&str]| -> Result<Vec<(&str, &str)>, SpecError> {
+            let mut params: Vec<(&str, &str)> = Vec::new();
+            for kv in rest.split(',').map(str::trim).filter(|kv| !kv.is_empty()) {
+                let (key, value) = kv
+                    .split_once('=')
+                    .ok_or_else(|| SpecError::MalformedParameter(kv.to_string()))?;
+                let (key, value) = (key.trim(), value.trim());
+                if !allowed.contains(&key) {
+                    return Err(SpecError::UnknownParameter {
+                        agent: name.to_string(),
+                        key: key.to_string(),
+                    });
+                }
+                if params.iter().any(|&(k, _)| k == key) {
+                    return Err(SpecError::DuplicateParameter(key.to_string()));
+                }
+                params.push((key, value));
+            }
+            Ok(params)
+        };
+        match name {
+            "random" => params_of(&[]).map(|_| AgentSpec::Random),
+            "lookahead" => params_of(&[]).map(|_| AgentSpec::Lookahead),
+            "mcts" => {
+                let (mut n_simulations, mut c) = (Self::DEFAULT_SIMULATIONS, Self::DEFAULT_C);
+                for (key, value) in params_of(&["sims", "c"])? {
+                    match key {
+                        "sims" => {
+                            n_simulations = value.parse().map_err(|e| invalid(key, value, e))?;
+                            if n_simulations == 0 {
+                                return Err(invalid(key, value, "must be positive"));
+                            }
+                        }
+                        _ => {
+                            c = value.parse().map_err(|e| invalid(key, value, e))?;
+                            if !(c.is_finite() && c >= 0.0) {
+                                return Err(invalid(key, value, "must be finite and non-negative"));
+                            }
+                        }
+                    }
+                }
+                Ok(AgentSpec::Mcts { n_simulations, c })
+            }
+            "alphabeta" => {
+                let mut depth = Self::DEFAULT_DEPTH;
+                for (key, value) in params_of(&["depth"])? {
+                    depth = value.parse().map_err(|e| invalid(key, value, e))?;
+                    if !(1..=Self::MAX_DEPTH).contains(&depth) {
+                        return Err(invalid(key, value, format!("must be between 1 and {}", Self::MAX_DEPTH)));
+                    }
+                }
+                Ok(AgentSpec::AlphaBeta { depth })
+            }
+            "human" => params_of(&[]).map(|_| AgentSpec::Human),
+            _ => Err(SpecError::UnknownAgent(name.to_string())),
+        }
+    }
+}
+
+/// Canonical spec string, which parses back to the same spec.
+impl std::fmt::Display for AgentSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AgentSpec::Random => write!(f, "random"),
+            AgentSpec::Lookahead => write!(f, "lookahead"),
+            AgentSpec::Mcts { n_simulations, c } => write!(f, "mcts:sims={},c={}", n_simulations, c),
+            AgentSpec::AlphaBeta { depth } => write!(f, "alphabeta:depth={}", depth),
+            AgentSpec::Human => write!(f, "human"),
+        }
+    }
+}
+
+/// Parses one spec per line, skipping blank lines and `#` comments.
+pub fn parse_specs(text: &str) -> Result<Vec<AgentSpec>, SpecError> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i, line.split('#').next().unwrap_or("").trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(i, line)| {
+            line.parse().map_err(|e| SpecError::Line {
+                line: i + 1,
+                source: Box::new(e),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("random".parse(), Ok(AgentSpec::Random));
+        assert_eq!(" lookahead ".parse(), Ok(AgentSpec::Lookahead));
+        assert_eq!(
+            "mcts:sims=20000,c=1.2".parse(),
+            Ok(AgentSpec::Mcts { n_simulations: 20000, c: 1.2 })
+        );
+        assert_eq!(
+            "mcts:c=0.5".parse(),
+            Ok(AgentSpec::Mcts { n_simulations: AgentSpec::DEFAULT_SIMULATIONS, c: 0.5 })
+        );
+        assert_eq!("mcts".parse(), Ok(AgentSpec::Mcts { n_simulations: 10000, c: 1.4 }));
+        assert_eq!("alphabeta:depth=6".parse(), Ok(AgentSpec::AlphaBeta { depth: 6 }));
+        assert_eq!("human".parse(), Ok(AgentSpec::Human));
+        for spec in ["random", "mcts:sims=20000,c=1.2", "alphabeta:depth=6"] {
+            assert_eq!(spec.parse::<AgentSpec>().unwrap().to_string(), spec);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = |s: &str| s.parse::<AgentSpec>().unwrap_err();
+        assert_eq!(err("minimax"), SpecError::UnknownAgent("minimax".into()));
+        assert_eq!(err("mcts:sims"), SpecError::MalformedParameter("sims".into()));
+        assert_eq!(
+            err("random:depth=3"),
+            SpecError::UnknownParameter { agent: "random".into(), key: "depth".into() }
+        );
+        assert_eq!(err("mcts:c=1,c=2"), SpecError::DuplicateParameter("c".into()));
+        assert!(matches!(err("mcts:sims=-5"), SpecError::InvalidValue { .. }));
+        assert!(matches!(err("mcts:sims=0"), SpecError::InvalidValue { .. }));
+        assert!(matches!(err("mcts:c=nan"), SpecError::InvalidValue { .. }));
+        assert!(matches!(err("alphabeta:depth=0"), SpecError::InvalidValue { .. }));
+        assert_eq!(err("alphabeta:depth=x").to_string(), "invalid value \"x\" for depth: invalid digit found in string");
+    }
+
+    #[test]
+    fn test_parse_specs() {
+        let specs = parse_specs("# agents\nrandom\n\nmcts:sims=100 # quick\n").unwrap();
+        assert_eq!(specs, vec![AgentSpec::Random, AgentSpec::Mcts { n_simulations: 100, c: 1.4 }]);
+        assert_eq!(parse_specs("random\nfoo").unwrap_err().to_string(), "line 2: unknown agent \"foo\", expected one of random, lookahead, mcts, alphabeta, human");
+    }
+}
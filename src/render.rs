@@ -0,0 +1,170 @@
+//! Text pictures of boards for terminal UIs.
+use crate::board::{Board, Player, Pos, SquareSet};
+use std::fmt::{self, Display};
+
+const RESET: &str = "\x1b[0m";
+const LAST_MOVE: &str = "\x1b[1;31m";
+const FLIPPED: &str = "\x1b[33m";
+const HINT: &str = "\x1b[32m";
+
+pub fn symbol(p: Player) -> char {
+    match p {
+        Player::Black => '●',
+        Player::White => '○',
+    }
+}
+
+/// Legal moves of `p` in reading order, e.g. `D3 C4 F5 E6`, or `none` without any.
+pub fn legal_moves(board: &Board, p: Player) -> String {
+    let moves = board.valid_moves_fast(p);
+    let names: Vec<_> = Pos::iter().filter(|pos| moves.contains(**pos)).map(|pos| pos.to_string()).collect();
+    match names.is_empty() {
+        true => "none".to_string(),
+        false => names.join(" "),
+    }
+}
+
+/// A board with coordinates and disc counts, ready to print.
+///
+/// Legal moves of the player to move can be marked with `*`. The last move and the discs it
+/// flipped are highlighted with ANSI colors, or spelled out under the board without them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct View {
+    pub board: Board,
+    pub hints: Option<Player>,
+    /// Last move, with the player who made it and the discs it flipped.
+    pub last_move: Option<(Player, Option<Pos>, SquareSet)>,
+    pub color: bool,
+}
+
+impl View {
+    pub fn new(board: Board) -> Self {
+        Self {
+            board,
+            hints: None,
+            last_move: None,
+            color: false,
+        }
+    }
+
+    pub fn hints(mut self, p: Player) -> Self {
+        self.hints = Some(p);
+        self
+    }
+
+    /// Highlights `p` playing `pos`, `None` for a pass, and flipping `flips`.
+    pub fn last_move(mut self, p: Player, pos: Option<Pos>, flips: SquareSet) -> Self {
+        self.last_move = Some((p, pos, flips));
+        self
+    }
+
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+}
+
+impl Display for View {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hints = self.hints.map_or(SquareSet::default(), |p| self.board.valid_moves_fast(p));
+        let (last, flips) = match self.last_move {
+            Some((_, pos, flips)) => (pos, flips),
+            None => (None, SquareSet::default()),
+        };
+        writeln!(f, "   A B C D E F G H")?;
+        for row in 0..8 {
+            write!(f, "{} ", row + 1)?;
+            for col in 0..8 {
+                let pos = Pos::from_row_col(row, col).expect("row and col below 8");
+                let (c, style) = match self.board.get(&pos) {
+                    Some(p) if last == Some(pos) => (symbol(p), LAST_MOVE),
+                    Some(p) if flips.contains(pos) => (symbol(p), FLIPPED),
+                    Some(p) => (symbol(p), ""),
+                    None if hints.contains(pos) => ('*', HINT),
+                    None => ('.', ""),
+                };
+                if self.color && !style.is_empty() {
+                    write!(f, " {}{}{}", style, c, RESET)?;
+                } else {
+                    write!(f, " {}", c)?;
+                }
+            }
+            writeln!(f)?;
+        }
+        write!(
+            f,
+            "  {} {}  {} {}",
+            symbol(Player::Black),
+            self.board.count(Player::Black),
+            symbol(Player::White),
+            self.board.count(Player::White)
+        )?;
+        if let Some(p) = self.hints {
+            write!(f, "  {} to move", symbol(p))?;
+        }
+        // colours highlight a move, but nothing shows a pass
+        match self.last_move {
+            Some((p, None, _)) => write!(f, "\n  {} passed", symbol(p))?,
+            Some((p, Some(pos), flips)) if !self.color => {
+                write!(f, "\n  {} played {}, flipping", symbol(p), pos)?;
+                for pos in flips.iter() {
+                    write!(f, " {}", pos)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain() {
+        let board = Board::initial();
+        assert_eq!(
+            View::new(board).hints(Player::Black).to_string(),
+            [
+                "   A B C D E F G H",
+                "1  . . . . . . . .",
+                "2  . . . . . . . .",
+                "3  . . . * . . . .",
+                "4  . . * ○ ● . . .",
+                "5  . . . ● ○ * . .",
+                "6  . . . . * . . .",
+                "7  . . . . . . . .",
+                "8  . . . . . . . .",
+                "  ● 2  ○ 2  ● to move",
+            ]
+            .join("\n")
+        );
+        let flips = board.flips(Player::Black, Pos::D3);
+        let after = board.valid_moves(Player::Black).find(|a| a.at == Pos::D3).unwrap().board;
+        let text = View::new(after).last_move(Player::Black, Some(Pos::D3), flips).to_string();
+        assert!(text.ends_with("● 4  ○ 1\n  ● played D3, flipping D4"), "{}", text);
+        assert_eq!(legal_moves(&board, Player::Black), "D3 C4 F5 E6");
+        assert_eq!(legal_moves(&Board::from_disks(Pos::A1.into(), SquareSet::EMPTY), Player::Black), "none");
+    }
+
+    #[test]
+    fn test_color() {
+        let board = Board::initial();
+        let flips = board.flips(Player::Black, Pos::D3);
+        let after = board.valid_moves(Player::Black).find(|a| a.at == Pos::D3).unwrap().board;
+        let text = View::new(after)
+            .hints(Player::White)
+            .last_move(Player::Black, Some(Pos::D3), flips)
+            .color(true)
+            .to_string();
+        let row3 = text.lines().nth(3).unwrap();
+        assert!(row3.contains(&format!("{}●{}", LAST_MOVE, RESET)), "{:?}", row3);
+        let row4 = text.lines().nth(4).unwrap();
+        assert!(row4.contains(&format!("{}●{}", FLIPPED, RESET)), "{:?}", row4);
+        assert!(text.contains(&format!("{}*{}", HINT, RESET)));
+        assert!(!text.contains("played"));
+        let text = View::new(after).last_move(Player::White, None, SquareSet::EMPTY).color(true).to_string();
+        assert!(text.ends_with("\n  ○ passed"), "{}", text);
+    }
+}
@@ -0,0 +1,324 @@
+//! A game server for the line protocol of [`crate::command`].
+//!
+//! Clients are paired in the order they connect. Each pair plays a match of several games,
+//! alternating colours, and every game ends with `END` sent to both players:
+//! - `DOUBLE_PASS` when neither player can move, the winner having more discs;
+//! - `ILLEGAL_MOVE` when a player sends an illegal move, a pass while it can move, or
+//!   anything else than `MOVE`, and loses;
+//! - `TIMEOUT` when a player runs out of time, and loses.
+//!
+//! Each player has its own clock for the whole game, running from when the server asks for
+//! its move (`START` for Black, the opponent's `MOVE` otherwise) until its reply. A timeout
+//! also ends the match, since the late move would otherwise be read as part of the next game.
+//! The match ends with `BYE` and the two players' disc margins, wins and losses.
+use crate::board::{Board, Player, Pos};
+use crate::command::{Cmd, Milliseconds, Score, Stat, Wl};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+/// Settings of every match of a server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServerConfig {
+    pub n_games: usize,
+    /// Thinking time of each player for a whole game.
+    pub time_limit: Duration,
+}
+
+impl ServerConfig {
+    pub fn new(n_games: usize, time_limit: Duration) -> Self {
+        Self { n_games, time_limit }
+    }
+}
+
+/// How a game ended, from Black's point of view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameOutcome {
+    pub winner: Option<Player>,
+    pub black_score: u32,
+    pub white_score: u32,
+    pub reason: &'static str,
+}
+
+struct Client {
+    name: String,
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+}
+
+enum Reply {
+    Move(Option<Pos>),
+    Timeout,
+    Invalid,
+}
+
+impl Client {
+    /// Waits for the `OPEN <name>` of a new client.
+    fn open(stream: TcpStream) -> io::Result<Self> {
+        // replies are single short lines, which Nagle's algorithm would hold back
+        stream.set_nodelay(true)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let name = match Cmd::try_from(line.as_str()) {
+            Ok(Cmd::Open { name }) => name,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("expected OPEN <name>, got {:?}", line.trim_end()),
+                ))
+            }
+        };
+        Ok(Self {
+            name,
+            reader,
+            writer: BufWriter::new(stream),
+        })
+    }
+    fn send(&mut self, cmd: &Cmd) -> io::Result<()> {
+        writeln!(self.writer, "{}", cmd)?;
+        self.writer.flush()
+    }
+    fn receive(&mut self, timeout: Duration) -> io::Result<Reply> {
+        self.reader
+            .get_ref()
+            .set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(_) => Ok(match Cmd::try_from(line.as_str()) {
+                Ok(Cmd::Move(pos)) => Reply::Move(pos),
+                _ => Reply::Invalid,
+            }),
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => Ok(Reply::Timeout),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn milliseconds(d: Duration) -> Milliseconds {
+    Milliseconds(d.as_millis().min(u32::MAX as u128) as u32)
+}
+
+/// Outcome of a game lost by `loser` before its end.
+fn forfeit(board: &Board, loser: Player, reason: &'static str) -> GameOutcome {
+    GameOutcome {
+        winner: Some(loser.opponent()),
+        black_score: board.count(Player::Black),
+        white_score: board.count(Player::White),
+        reason,
+    }
+}
+
+/// Plays one game, `clients[0]` being Black.
+fn play_game(clients: [&mut Client; 2], time_limit: Duration) -> io::Result<GameOutcome> {
+    let mut remaining = [time_limit; 2];
+    for p in [Player::Black, Player::White] {
+        let opponent_name = clients[p.opponent() as usize].name.clone();
+        clients[p as usize].send(&Cmd::start(p, opponent_name, milliseconds(time_limit)))?;
+    }
+    let mut board = Board::initial();
+    let mut p = Player::Black;
+    let outcome = loop {
+        let i = p as usize;
+        let start = Instant::now();
+        let reply = clients[i].receive(remaining[i])?;
+        let elapsed = start.elapsed();
+        if matches!(reply, Reply::Timeout) || elapsed > remaining[i] {
+            break forfeit(&board, p, "TIMEOUT");
+        }
+        remaining[i] -= elapsed;
+        let pos = match reply {
+            Reply::Move(Some(pos)) => {
+                let next = board.valid_moves(p).find(|a| a.at == pos).map(|a| a.board);
+                match next {
+                    Some(next) => board = next,
+                    None => break forfeit(&board, p, "ILLEGAL_MOVE"),
+                }
+                Some(pos)
+            }
+            Reply::Move(None) if board.valid_moves_fast(p).is_empty() => None,
+            _ => break forfeit(&board, p, "ILLEGAL_MOVE"),
+        };
+        clients[i].send(&Cmd::ack(milliseconds(remaining[i])))?;
+        if board.is_final() {
+            let (black_score, white_score) = (board.count(Player::Black), board.count(Player::White));
+            break GameOutcome {
+                winner: match black_score.cmp(&white_score) {
+                    std::cmp::Ordering::Greater => Some(Player::Black),
+                    std::cmp::Ordering::Less => Some(Player::White),
+                    std::cmp::Ordering::Equal => None,
+                },
+                black_score,
+                white_score,
+                reason: "DOUBLE_PASS",
+            };
+        }
+        clients[p.opponent() as usize].send(&Cmd::Move(pos))?;
+        p.flip();
+    };
+    for p in [Player::Black, Player::White] {
+        let (mine, theirs) = match p {
+            Player::Black => (outcome.black_score, outcome.white_score),
+            Player::White => (outcome.white_score, outcome.black_score),
+        };
+        let result = match outcome.winner {
+            Some(w) if w == p => Wl::Win,
+            Some(_) => Wl::Lose,
+            None => Wl::Tie,
+        };
+        clients[p as usize].send(&Cmd::end(result, mine, theirs, outcome.reason))?;
+    }
+    Ok(outcome)
+}
+
+/// Plays a match between two freshly connected clients, `a` being Black in even games, and
+/// calls `on_game` after each game. Returns the statistics sent with `BYE`.
+pub fn play_match(
+    a: TcpStream,
+    b: TcpStream,
+    config: &ServerConfig,
+    mut on_game: impl FnMut(&str, &str, &GameOutcome),
+) -> io::Result<Vec<Stat>> {
+    let mut clients = [Client::open(a)?, Client::open(b)?];
+    let mut stat: Vec<_> = clients
+        .iter()
+        .map(|c| Stat {
+            name: c.name.clone(),
+            score: Score(0),
+            n_win: 0,
+            n_lose: 0,
+        })
+        .collect();
+    for i in 0..config.n_games {
+        // index of the client playing Black
+        let black = i % 2;
+        let [a, b] = &mut clients;
+        let players = if black == 0 { [a, b] } else { [b, a] };
+        let outcome = play_game(players, config.time_limit)?;
+        on_game(&clients[black].name, &clients[1 - black].name, &outcome);
+        let margin = outcome.black_score as i32 - outcome.white_score as i32;
+        stat[black].score.0 += margin;
+        stat[1 - black].score.0 -= margin;
+        match outcome.winner {
+            Some(Player::Black) => {
+                stat[black].n_win += 1;
+                stat[1 - black].n_lose += 1;
+            }
+            Some(Player::White) => {
+                stat[1 - black].n_win += 1;
+                stat[black].n_lose += 1;
+            }
+            None => {}
+        }
+        if outcome.reason == "TIMEOUT" {
+            break;
+        }
+    }
+    for client in &mut clients {
+        client.send(&Cmd::Bye { stat: stat.clone() })?;
+    }
+    Ok(stat)
+}
+
+/// Accepts clients forever, pairing them in the order they connect and playing each match on
+/// its own thread.
+pub fn serve(listener: TcpListener, config: ServerConfig) -> io::Result<()> {
+    let mut waiting: Option<TcpStream> = None;
+    for stream in listener.incoming() {
+        let stream = stream?;
+        match waiting.take() {
+            None => waiting = Some(stream),
+            Some(first) => {
+                std::thread::spawn(move || {
+                    let result = play_match(first, stream, &config, |black, white, outcome| {
+                        eprintln!(
+                            "{} (Black) {} - {} {} (White): {}",
+                            black, outcome.black_score, outcome.white_score, white, outcome.reason
+                        );
+                    });
+                    match result {
+                        Ok(stat) => eprintln!("{}", Cmd::Bye { stat }),
+                        Err(e) => eprintln!("match aborted: {}", e),
+                    }
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::RandomAgent;
+    use crate::command::Session;
+
+    /// Runs `play_match` on a local port against two raw connections driven by `a` and `b`.
+    fn with_clients<A, B>(config: ServerConfig, a: A, b: B) -> (io::Result<Vec<Stat>>, Vec<GameOutcome>)
+    where
+        A: FnOnce(TcpStream) + Send + 'static,
+        B: FnOnce(TcpStream) + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let a = std::thread::spawn(move || a(TcpStream::connect(addr).unwrap()));
+        let (sa, _) = listener.accept().unwrap();
+        let b = std::thread::spawn(move || b(TcpStream::connect(addr).unwrap()));
+        let (sb, _) = listener.accept().unwrap();
+        let mut outcomes = Vec::new();
+        let stat = play_match(sa, sb, &config, |_, _, o| outcomes.push(o.clone()));
+        a.join().unwrap();
+        b.join().unwrap();
+        (stat, outcomes)
+    }
+
+    fn random_client(name: &'static str) -> impl FnOnce(TcpStream) + Send {
+        move |stream| Session::new(name).launch(&mut RandomAgent, &stream).unwrap()
+    }
+
+    #[test]
+    fn test_match_between_sessions() {
+        let config = ServerConfig::new(4, Duration::from_secs(10));
+        let (stat, outcomes) = with_clients(config, random_client("a"), random_client("b"));
+        let stat = stat.unwrap();
+        assert_eq!(outcomes.len(), 4);
+        assert!(outcomes.iter().all(|o| o.reason == "DOUBLE_PASS"));
+        assert_eq!((stat[0].name.as_str(), stat[1].name.as_str()), ("a", "b"));
+        assert_eq!(stat[0].score.0, -stat[1].score.0);
+        assert_eq!(stat[0].n_win, stat[1].n_lose);
+        let decided = outcomes.iter().filter(|o| o.winner.is_some()).count() as u32;
+        assert_eq!(stat[0].n_win + stat[1].n_win, decided);
+    }
+
+    #[test]
+    fn test_illegal_move_and_timeout() {
+        let config = ServerConfig::new(2, Duration::from_millis(300));
+        // Black in the first game: plays an illegal move, then never answers as White
+        let cheater = |stream: TcpStream| {
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = &stream;
+            writeln!(writer, "OPEN cheater").unwrap();
+            let mut lines = Vec::new();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 {
+                if line.starts_with("START BLACK") {
+                    writeln!(writer, "MOVE A1").unwrap();
+                }
+                lines.push(std::mem::take(&mut line));
+            }
+            assert_eq!(lines[0].trim(), "START BLACK honest 300");
+            assert_eq!(lines[1].trim(), "END LOSE 2 2 ILLEGAL_MOVE");
+            assert_eq!(lines[2].trim(), "START WHITE honest 300");
+            assert!(lines[3].starts_with("MOVE "));
+            assert_eq!(lines[4].trim(), "END LOSE 1 4 TIMEOUT");
+            assert_eq!(lines[5].trim(), "BYE cheater -3 0 2 honest 3 2 0");
+        };
+        let (stat, outcomes) = with_clients(config, cheater, random_client("honest"));
+        let stat = stat.unwrap();
+        assert_eq!(outcomes.iter().map(|o| o.reason).collect::<Vec<_>>(), ["ILLEGAL_MOVE", "TIMEOUT"]);
+        assert_eq!(outcomes[0].winner, Some(Player::White));
+        assert_eq!(outcomes[1].winner, Some(Player::Black));
+        assert_eq!((stat[0].n_lose, stat[1].n_win), (2, 2));
+    }
+}
@@ -0,0 +1,26 @@
+use clap::Parser;
+use reversi::server::{self, ServerConfig};
+use std::time::Duration;
+
+#[derive(Parser, Debug)]
+#[command(about = "Reversi game server pairing clients as they connect")]
+struct Cli {
+    #[arg(
+        short = 'p',
+        default_value_t = 3000,
+        help = "port number",
+        value_parser = clap::value_parser!(u16).range(1..)
+    )]
+    port: u16,
+    #[arg(short = 'n', default_value_t = 2, help = "number of games per match")]
+    n_games: usize,
+    #[arg(short = 't', default_value_t = 60.0, help = "thinking time of each player per game, in seconds")]
+    time_limit: f64,
+}
+
+fn main() -> std::io::Result<()> {
+    let cli = Cli::parse();
+    let listener = std::net::TcpListener::bind(("0.0.0.0", cli.port))?;
+    eprintln!("Listening on port {}...", cli.port);
+    server::serve(listener, ServerConfig::new(cli.n_games, Duration::from_secs_f64(cli.time_limit)))
+}
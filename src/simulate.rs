@@ -1,52 +1,322 @@
-use crate::agent::Agent;
-use crate::board::{Board, Player, Action};
+use crate::agent::{Agent, GameEnd};
+use crate::board::{Action, Board, ParsePositionError, Player, Pos, Position};
+use std::fmt::{self, Display};
 
-pub fn simulate<'a>(
+/// Everything that happened in a game played by [`simulate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameRecord {
+    pub start: Board,
+    pub first_player: Player,
+    /// Every turn in order, with the player to move and its move, `None` for a pass.
+    pub turns: Vec<(Player, Option<Action>)>,
+    pub final_board: Board,
+}
+
+impl GameRecord {
+    /// Number of moves played, passes excluded.
+    pub fn n_moves(&self) -> usize {
+        self.turns.iter().filter(|(_, action)| action.is_some()).count()
+    }
+    /// Discs of Black and of White at the end of the game.
+    pub fn score(&self) -> (u32, u32) {
+        (self.final_board.count(Player::Black), self.final_board.count(Player::White))
+    }
+    pub fn winner(&self) -> Option<Player> {
+        let (black, white) = self.score();
+        match black.cmp(&white) {
+            std::cmp::Ordering::Greater => Some(Player::Black),
+            std::cmp::Ordering::Less => Some(Player::White),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+    /// Replays `moves`, a move sequence such as `F5D6C3D3` where whitespace is ignored and
+    /// passes are left out, from `start` with `first_player` to move.
+    pub fn from_moves(start: Board, first_player: Player, moves: &str) -> Result<Self, ParseGameError> {
+        let squares: Vec<char> = moves.chars().filter(|c| !c.is_whitespace()).collect();
+        let mut record = GameRecord {
+            start,
+            first_player,
+            turns: Vec::new(),
+            final_board: start,
+        };
+        let mut p = first_player;
+        for chunk in squares.chunks(2) {
+            let s: String = chunk.iter().collect();
+            let at = Pos::try_from(s.as_str()).map_err(|_| ParseGameError::Square(s))?;
+            if record.final_board.valid_moves_fast(p).is_empty() {
+                record.turns.push((p, None));
+                p.flip();
+            }
+            let action = record.final_board.valid_moves(p).find(|a| a.at == at);
+            let action = action.ok_or(ParseGameError::Illegal { ply: record.turns.len() + 1, at })?;
+            record.turns.push((p, Some(action)));
+            record.final_board = action.board;
+            p.flip();
+        }
+        Ok(record)
+    }
+    /// Player to move after the last turn, the other one if it has to pass.
+    pub fn to_move(&self) -> Player {
+        let p = self.turns.last().map_or(self.first_player, |&(p, _)| p.opponent());
+        match self.final_board.valid_moves_fast(p).is_empty() && !self.final_board.is_final() {
+            true => p.opponent(),
+            false => p,
+        }
+    }
+}
+
+#[derive(thiserror::Error, Clone, PartialEq, Eq, Debug)]
+pub enum ParseGameError {
+    #[error("bad start position: {0}")]
+    Position(#[from] ParsePositionError),
+    #[error("{0:?} is not a square")]
+    Square(String),
+    #[error("move {ply}, {at}, is illegal")]
+    Illegal { ply: usize, at: Pos },
+}
+
+/// The moves, passes left out, after the start [`Position`] unless the game starts from the
+/// initial position with Black to move, e.g. `F5D6C3D3...`.
+impl Display for GameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if (self.start, self.first_player) != (Board::initial(), Player::Black) {
+            let start = Position {
+                board: self.start,
+                to_move: self.first_player,
+            };
+            write!(f, "{} ", start)?;
+        }
+        for (_, action) in &self.turns {
+            if let Some(action) = action {
+                write!(f, "{}", action.at)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for GameRecord {
+    type Err = ParseGameError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens: Vec<&str> = s.split_whitespace().collect();
+        match tokens[..] {
+            [squares, to_move, ref moves @ ..] if squares.len() == 64 => {
+                let start: Position = format!("{} {}", squares, to_move).parse()?;
+                GameRecord::from_moves(start.board, start.to_move, &moves.concat())
+            }
+            _ => GameRecord::from_moves(Board::initial(), Player::Black, s),
+        }
+    }
+}
+
+/// Plays a game from `board` with `first_player` to move until neither player can move.
+///
+/// The agents go through the same lifecycle as in [`Session::launch`]: `initialize` before
+/// the game, `opponent_move_callback` after each of the other's moves and passes, and
+/// `game_end` followed by `initialize` once the game is over. `call_back` is called before every turn is
+/// applied.
+///
+/// [`Session::launch`]: crate::command::Session::launch
+pub fn simulate(
     board: Board,
-    black: &mut impl Agent,
-    white: &mut impl Agent,
-    n_steps: &mut usize,
+    first_player: Player,
+    black: &mut dyn Agent,
+    white: &mut dyn Agent,
     call_back: &mut impl FnMut(Board, Player, Option<Action>),
-) -> Option<Player> {
+) -> GameRecord {
+    let mut record = GameRecord {
+        start: board,
+        first_player,
+        turns: Vec::new(),
+        final_board: board,
+    };
+    black.initialize();
+    white.initialize();
     let mut board = board;
-    let mut p = Player::Black;
+    let mut p = first_player;
     let mut passed = false;
-    *n_steps = 0;
-    loop {
-        if let Some(action) = match p {
-            Player::Black => black.select_move(&board, p),
-            Player::White => white.select_move(&board, p),
-        } {
-            // println!("{:?}: {:?}", p, action.at);
-            // println!("{:?}", action.board);
-            call_back(board, p, Some(action));
+    while !board.is_final() {
+        let (me, opponent): (&mut dyn Agent, &mut dyn Agent) = match p {
+            Player::Black => (&mut *black, &mut *white),
+            Player::White => (&mut *white, &mut *black),
+        };
+        let action = me.select_move(&board, p);
+        call_back(board, p, action);
+        opponent.opponent_move_callback(action);
+        record.turns.push((p, action));
+        if let Some(action) = action {
             board = action.board;
-            *n_steps += 1;
             passed = false;
+        } else if passed {
+            // both agents passed without the game being over
+            break;
         } else {
-            call_back(board, p, None);
-            if passed {
-                break;
-            }
             passed = true;
         }
         p.flip();
     }
-    // println!();
-    match board.count(Player::Black).cmp(&board.count(Player::White)) {
-        std::cmp::Ordering::Greater => Some(Player::Black),
-        std::cmp::Ordering::Less => Some(Player::White),
-        std::cmp::Ordering::Equal => None,
-    }
+    record.final_board = board;
+    black.game_end(&GameEnd::from_board(&board, Player::Black, "DOUBLE_PASS"));
+    white.game_end(&GameEnd::from_board(&board, Player::White, "DOUBLE_PASS"));
+    black.initialize();
+    white.initialize();
+    record
 }
 
+/// Plays a game from `$board` with Black to move, stores the number of moves in `$n_steps`
+/// and evaluates to the winner, `None` for a draw.
 #[macro_export]
 macro_rules! simulate {
-    ($board:expr, $black:expr, $white:expr, $n_steps:expr, $call_back:expr) => {
-        crate::simulate::simulate($board, $black, $white, $n_steps, $call_back)
-    };
+    ($board:expr, $black:expr, $white:expr, $n_steps:expr, $call_back:expr) => {{
+        let record = $crate::simulate::simulate($board, $crate::board::Player::Black, $black, $white, $call_back);
+        *$n_steps = record.n_moves();
+        record.winner()
+    }};
     ($board:expr, $black:expr, $white:expr, $n_steps:expr) => {
-        crate::simulate::simulate($board, $black, $white, $n_steps, &mut |_, _, _| {})
+        $crate::simulate!($board, $black, $white, $n_steps, &mut |_, _, _| {})
     };
-    
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::RandomAgent;
+    use crate::board::SquareSet;
+
+    /// Checks that every move it is told about continues the game it has seen so far.
+    struct Watcher {
+        board: Board,
+        to_move: Player,
+        me: Player,
+    }
+    impl Agent for Watcher {
+        fn opponent_move_callback(&mut self, action: Option<Action>) {
+            assert_eq!(self.to_move, self.me.opponent());
+            let moves: Vec<_> = self.board.valid_moves(self.to_move).collect();
+            match action {
+                Some(action) => {
+                    assert!(moves.contains(&action));
+                    self.board = action.board;
+                }
+                None => assert!(moves.is_empty()),
+            }
+            self.to_move = self.me;
+        }
+        fn select_move(&mut self, board: &Board, p: Player) -> Option<Action> {
+            assert_eq!((*board, p), (self.board, self.to_move));
+            let action = RandomAgent.select_move(board, p);
+            if let Some(action) = action {
+                self.board = action.board;
+            }
+            self.to_move = p.opponent();
+            action
+        }
+    }
+
+    #[test]
+    fn test_record() {
+        let start = Board::initial().valid_moves(Player::Black).next().unwrap().board;
+        let mut black = Watcher { board: start, to_move: Player::White, me: Player::Black };
+        let mut white = Watcher { board: start, to_move: Player::White, me: Player::White };
+        let mut n_calls = 0;
+        let record = simulate(start, Player::White, &mut black, &mut white, &mut |_, _, _| n_calls += 1);
+        assert_eq!(record.turns.len(), n_calls);
+        assert_eq!(record.turns[0].0, Player::White);
+        assert!(record.final_board.is_final());
+        assert_eq!(record.final_board, black.board);
+        let (b, w) = record.score();
+        assert_eq!((b, w), (record.final_board.count(Player::Black), record.final_board.count(Player::White)));
+        let mut board = record.start;
+        for &(p, action) in &record.turns {
+            if let Some(action) = action {
+                assert!(board.valid_moves(p).any(|a| a == action));
+                board = action.board;
+            }
+        }
+        assert_eq!(board, record.final_board);
+        assert_eq!(record.n_moves(), (b + w - 5) as usize);
+    }
+
+    #[test]
+    fn test_record_text() {
+        let record = simulate(Board::initial(), Player::Black, &mut RandomAgent, &mut RandomAgent, &mut |_, _, _| {});
+        let text = record.to_string();
+        assert_eq!(text.len(), 2 * record.n_moves());
+        assert_eq!(text.parse::<GameRecord>(), Ok(record));
+        // from an opening, with passes
+        let board = Board::from_disks(Pos::C1.into(), SquareSet::from(Pos::A1) | Pos::B1);
+        let record = simulate(board, Player::Black, &mut RandomAgent, &mut RandomAgent, &mut |_, _, _| {});
+        let text = record.to_string();
+        assert_eq!(text, format!("OOX{} X D1", "-".repeat(61)));
+        assert_eq!(record.turns[0], (Player::Black, None));
+        assert_eq!(text.parse::<GameRecord>(), Ok(record));
+        assert_eq!("F5D6 c3".parse::<GameRecord>().map(|r| r.to_move()), Ok(Player::White));
+        assert_eq!("F5Z9".parse::<GameRecord>(), Err(ParseGameError::Square("Z9".to_string())));
+        assert_eq!("F5D".parse::<GameRecord>(), Err(ParseGameError::Square("D".to_string())));
+        assert_eq!("F5A1".parse::<GameRecord>(), Err(ParseGameError::Illegal { ply: 2, at: Pos::A1 }));
+        let bad_side = format!("{} Q F5", "-".repeat(64));
+        assert!(matches!(bad_side.parse::<GameRecord>(), Err(ParseGameError::Position(_))));
+    }
+
+    /// Logs every call it receives.
+    #[derive(Default)]
+    struct Logger(Vec<String>);
+    impl Agent for Logger {
+        fn initialize(&mut self) {
+            self.0.push("initialize".into());
+        }
+        fn opponent_move_callback(&mut self, action: Option<Action>) {
+            self.0.push(format!("opponent {:?}", action.map(|a| a.at)));
+        }
+        fn select_move(&mut self, board: &Board, p: Player) -> Option<Action> {
+            let action = board.valid_moves(p).next();
+            self.0.push(format!("select {:?}", action.map(|a| a.at)));
+            action
+        }
+        fn game_end(&mut self, end: &GameEnd) {
+            self.0.push(format!("end {:?} {} {} {}", end.result, end.my_score, end.opponent_score, end.reason));
+        }
+    }
+
+    #[test]
+    fn test_lifecycle_with_pass() {
+        use crate::board::{Pos, SquareSet};
+        // Black on C1 cannot move; White takes D1 and with it every disc
+        let board = Board::from_disks(Pos::C1.into(), SquareSet::from(Pos::A1) | Pos::B1);
+        let (mut black, mut white) = (Logger::default(), Logger::default());
+        let record = simulate(board, Player::Black, &mut black, &mut white, &mut |_, _, _| {});
+        assert_eq!(record.turns.iter().map(|&(p, a)| (p, a.map(|a| a.at))).collect::<Vec<_>>(), vec![
+            (Player::Black, None),
+            (Player::White, Some(Pos::D1)),
+        ]);
+        assert_eq!(record.score(), (0, 4));
+        assert_eq!(black.0, [
+            "initialize",
+            "select None",
+            "opponent Some(D1)",
+            "end Lose 0 4 DOUBLE_PASS",
+            "initialize",
+        ]);
+        assert_eq!(white.0, [
+            "initialize",
+            "opponent None",
+            "select Some(D1)",
+            "end Win 4 0 DOUBLE_PASS",
+            "initialize",
+        ]);
+    }
+
+    #[test]
+    fn test_macro() {
+        let mut n_steps = 0;
+        let mut n_calls = 0;
+        let winner = simulate!(Board::initial(), &mut RandomAgent, &mut RandomAgent, &mut n_steps, &mut |b, p, a| {
+            assert!(a.is_some() || b.valid_moves_fast(p).is_empty());
+            n_calls += a.is_some() as usize;
+        });
+        assert_eq!(n_steps, n_calls);
+        assert!((1..=60).contains(&n_steps));
+        // the macro still evaluates to the winner
+        let _: Option<Player> = winner;
+    }
 }
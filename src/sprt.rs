@@ -0,0 +1,233 @@
+//! Sequential probability ratio test between a baseline and a candidate agent.
+//!
+//! Games are played until the log-likelihood ratio of "the candidate is `elo1` stronger" over
+//! "the candidate is `elo0` stronger" leaves the interval given by the error rates `alpha` and
+//! `beta`, as in the GSPRT used by Fishtest: each game score (1, 1/2 or 0) is modelled as
+//! normally distributed with the observed variance, regularised by one pseudo-win and one
+//! pseudo-loss so that a few lucky games, or a clean sweep, cannot make it vanish.
+use crate::agent::Agent;
+use crate::match_runner::{self, GameSummary, MatchConfig, MatchStats};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+/// Hypotheses and error rates of a test.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SprtConfig {
+    pub elo0: f64,
+    pub elo1: f64,
+    /// Probability of accepting H1 when H0 holds.
+    pub alpha: f64,
+    /// Probability of accepting H0 when H1 holds.
+    pub beta: f64,
+    /// Stop with [`SprtResult::Inconclusive`] after this many games.
+    pub max_games: Option<usize>,
+}
+
+impl SprtConfig {
+    pub fn new(elo0: f64, elo1: f64) -> Self {
+        Self {
+            elo0,
+            elo1,
+            alpha: 0.05,
+            beta: 0.05,
+            max_games: None,
+        }
+    }
+    pub fn alpha(mut self, alpha: f64) -> Self {
+        self.alpha = alpha;
+        self
+    }
+    pub fn beta(mut self, beta: f64) -> Self {
+        self.beta = beta;
+        self
+    }
+    pub fn max_games(mut self, max_games: usize) -> Self {
+        self.max_games = Some(max_games);
+        self
+    }
+    /// Log-likelihood ratios below the first bound accept H0, above the second accept H1.
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+    /// Log-likelihood ratio of H1 over H0 given `stats`, from the candidate's point of view.
+    pub fn llr(&self, stats: &MatchStats) -> f64 {
+        let n = stats.games() as f64;
+        let s = stats.score();
+        // the pseudo-games keep the variance positive and damp it while there are few games
+        let variance = ((stats.wins + 1) as f64 * (1.0 - s).powi(2)
+            + stats.draws as f64 * (0.5 - s).powi(2)
+            + (stats.losses + 1) as f64 * s.powi(2))
+            / (n + 2.0);
+        let s0 = expected_score(self.elo0);
+        let s1 = expected_score(self.elo1);
+        n * (s1 - s0) * (2.0 * s - s0 - s1) / (2.0 * variance)
+    }
+    /// Decision given `stats`, or `None` to keep playing.
+    pub fn decide(&self, stats: &MatchStats) -> Option<SprtResult> {
+        let (lower, upper) = self.bounds();
+        let llr = self.llr(stats);
+        if llr <= lower {
+            Some(SprtResult::AcceptH0)
+        } else if llr >= upper {
+            Some(SprtResult::AcceptH1)
+        } else if self.max_games.is_some_and(|max| stats.games() as usize >= max) {
+            Some(SprtResult::Inconclusive)
+        } else {
+            None
+        }
+    }
+}
+
+/// Expected score of a player `elo` stronger than its opponent.
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SprtResult {
+    /// The candidate is at most `elo0` stronger.
+    AcceptH0,
+    /// The candidate is at least `elo1` stronger.
+    AcceptH1,
+    /// `max_games` were played without a decision.
+    Inconclusive,
+}
+
+/// Final state of a test.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SprtOutcome {
+    pub result: SprtResult,
+    pub llr: f64,
+    /// Totals from the candidate's point of view.
+    pub stats: MatchStats,
+}
+
+/// Plays games between fresh agents from `make_candidate` and `make_baseline` on `threads`
+/// threads until `sprt` reaches a decision, with starting positions and colours from `config`
+/// (whose `n_games` is ignored).
+///
+/// `on_game(game, stats, llr)` is called on the calling thread after each counted game. Games
+/// still running when the decision is reached are discarded.
+pub fn run<C, B>(
+    sprt: &SprtConfig,
+    config: &MatchConfig,
+    make_candidate: C,
+    make_baseline: B,
+    threads: usize,
+    mut on_game: impl FnMut(&GameSummary, &MatchStats, f64),
+) -> SprtOutcome
+where
+    C: Fn() -> Box<dyn Agent> + Sync,
+    B: Fn() -> Box<dyn Agent> + Sync,
+{
+    let next_game = AtomicUsize::new(0);
+    let done = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+    let mut stats = MatchStats::default();
+    let mut result = SprtResult::Inconclusive;
+    std::thread::scope(|s| {
+        for _ in 0..threads.max(1) {
+            let sender = sender.clone();
+            let (next_game, done) = (&next_game, &done);
+            let (make_candidate, make_baseline) = (&make_candidate, &make_baseline);
+            s.spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    let i = next_game.fetch_add(1, Ordering::Relaxed);
+                    if sprt.max_games.is_some_and(|max| i >= max) {
+                        break;
+                    }
+                    let (board, a_is_black) = config.game(i);
+                    let game =
+                        match_runner::play_game(&mut make_candidate(), &mut make_baseline(), board, a_is_black, i);
+                    if sender.send(game).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+        for game in &receiver {
+            stats.add(&game);
+            on_game(&game, &stats, sprt.llr(&stats));
+            if let Some(decision) = sprt.decide(&stats) {
+                result = decision;
+                done.store(true, Ordering::Relaxed);
+                break;
+            }
+        }
+        // dropping the receiver makes the remaining workers stop after their current game
+        drop(receiver);
+    });
+    SprtOutcome {
+        result,
+        llr: sprt.llr(&stats),
+        stats,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{OneStepLookaheadAgent, RandomAgent, SeededRandomAgent};
+
+    #[test]
+    fn test_llr() {
+        let sprt = SprtConfig::new(0.0, 10.0);
+        let (lower, upper) = sprt.bounds();
+        assert!((lower + 2.944).abs() < 1e-3 && (upper - 2.944).abs() < 1e-3);
+        assert_eq!(sprt.llr(&MatchStats::default()), 0.0);
+        let even = MatchStats { wins: 500, losses: 500, ..Default::default() };
+        let strong = MatchStats { wins: 600, losses: 400, ..Default::default() };
+        assert!(sprt.llr(&even) < 0.0);
+        assert!(sprt.llr(&strong) > sprt.llr(&even));
+        assert_eq!(sprt.decide(&strong), Some(SprtResult::AcceptH1));
+        assert_eq!(SprtConfig::new(100.0, 110.0).decide(&even), Some(SprtResult::AcceptH0));
+        let few = MatchStats { wins: 3, losses: 2, ..Default::default() };
+        assert_eq!(sprt.decide(&few), None);
+        assert_eq!(sprt.max_games(5).decide(&few), Some(SprtResult::Inconclusive));
+    }
+
+    #[test]
+    fn test_sweep() {
+        let sprt = SprtConfig::new(0.0, 10.0);
+        let decided = |stats: fn(u32) -> MatchStats| (1..=100).find_map(|n| sprt.decide(&stats(n)).map(|r| (n, r)));
+        let (n, result) = decided(|n| MatchStats { wins: n, ..Default::default() }).unwrap();
+        assert_eq!(result, SprtResult::AcceptH1);
+        assert!(n <= 25, "{}", n);
+        let (n, result) = decided(|n| MatchStats { losses: n, ..Default::default() }).unwrap();
+        assert_eq!(result, SprtResult::AcceptH0);
+        assert!(n <= 25, "{}", n);
+        // a few early wins are not enough
+        assert_eq!(sprt.decide(&MatchStats { wins: 4, ..Default::default() }), None);
+    }
+
+    #[test]
+    fn test_run() {
+        let sprt = SprtConfig::new(0.0, 50.0).max_games(2000);
+        let config = MatchConfig::new(0);
+        let mut n = 0;
+        let outcome = run(
+            &sprt,
+            &config,
+            || Box::new(OneStepLookaheadAgent),
+            || Box::new(RandomAgent),
+            4,
+            |_, stats, _| {
+                n += 1;
+                assert_eq!(stats.games(), n);
+            },
+        );
+        assert_eq!(outcome.result, SprtResult::AcceptH1);
+        assert_eq!(outcome.stats.games(), n);
+        assert!(outcome.llr >= sprt.bounds().1);
+        // seeded agents on one thread play the same games every time
+        let sprt = SprtConfig::new(50.0, 100.0).max_games(2000);
+        let seed = AtomicUsize::new(0);
+        let seeded = || -> Box<dyn Agent> { Box::new(SeededRandomAgent::new(seed.fetch_add(1, Ordering::Relaxed) as u64)) };
+        let outcome = run(&sprt, &config, seeded, seeded, 1, |_, _, _| ());
+        assert_eq!(outcome.result, SprtResult::AcceptH0);
+    }
+}
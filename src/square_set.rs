@@ -0,0 +1,214 @@
+use crate::board::{CompassRose, Pos, PosIter, Shift};
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not, Sub, SubAssign};
+
+/// A set of squares, stored as a bitboard with bit `pos as u8` set for each member.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct SquareSet(u64);
+
+impl SquareSet {
+    pub const EMPTY: SquareSet = SquareSet(0);
+    pub const FULL: SquareSet = SquareSet(u64::MAX);
+    pub const CORNERS: SquareSet = SquareSet(0x8100_0000_0000_0081);
+
+    pub const fn new(bits: u64) -> SquareSet {
+        SquareSet(bits)
+    }
+    pub const fn bits(self) -> u64 {
+        self.0
+    }
+    /// Number of squares in the set.
+    pub const fn len(self) -> usize {
+        self.0.count_ones() as usize
+    }
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+    pub fn contains(self, pos: Pos) -> bool {
+        self.0 & pos.mask() != 0
+    }
+    pub fn insert(&mut self, pos: Pos) {
+        self.0 |= pos.mask();
+    }
+    pub fn remove(&mut self, pos: Pos) {
+        self.0 &= !pos.mask();
+    }
+    pub fn is_subset(self, other: SquareSet) -> bool {
+        self.0 & other.0 == self.0
+    }
+    pub fn is_disjoint(self, other: SquareSet) -> bool {
+        self.0 & other.0 == 0
+    }
+    /// The member with the lowest bit index, if any.
+    pub fn first(self) -> Option<Pos> {
+        self.iter().next()
+    }
+    /// Iterates over the members from the lowest bit (H8) upwards.
+    pub fn iter(self) -> PosIter {
+        Pos::iter_mask(self.0)
+    }
+    /// Moves every member one square towards `d`; squares shifted off the board are dropped.
+    pub fn shift(self, d: CompassRose) -> SquareSet {
+        SquareSet(self.0.shift(&d))
+    }
+}
+
+impl From<u64> for SquareSet {
+    fn from(bits: u64) -> SquareSet {
+        SquareSet(bits)
+    }
+}
+impl From<SquareSet> for u64 {
+    fn from(set: SquareSet) -> u64 {
+        set.0
+    }
+}
+impl From<Pos> for SquareSet {
+    fn from(pos: Pos) -> SquareSet {
+        SquareSet(pos.mask())
+    }
+}
+impl FromIterator<Pos> for SquareSet {
+    fn from_iter<I: IntoIterator<Item = Pos>>(iter: I) -> SquareSet {
+        SquareSet(iter.into_iter().fold(0, |acc, pos| acc | pos.mask()))
+    }
+}
+impl Extend<Pos> for SquareSet {
+    fn extend<I: IntoIterator<Item = Pos>>(&mut self, iter: I) {
+        for pos in iter {
+            self.insert(pos);
+        }
+    }
+}
+impl IntoIterator for SquareSet {
+    type Item = Pos;
+    type IntoIter = PosIter;
+    fn into_iter(self) -> PosIter {
+        self.iter()
+    }
+}
+
+macro_rules! impl_set_op {
+    ($op:ident, $f:ident, $op_assign:ident, $f_assign:ident, $e:tt) => {
+        impl $op for SquareSet {
+            type Output = SquareSet;
+            fn $f(self, rhs: SquareSet) -> SquareSet {
+                SquareSet(self.0 $e rhs.0)
+            }
+        }
+        impl $op<Pos> for SquareSet {
+            type Output = SquareSet;
+            fn $f(self, rhs: Pos) -> SquareSet {
+                SquareSet(self.0 $e rhs.mask())
+            }
+        }
+        impl $op_assign for SquareSet {
+            fn $f_assign(&mut self, rhs: SquareSet) {
+                *self = $op::$f(*self, rhs);
+            }
+        }
+        impl $op_assign<Pos> for SquareSet {
+            fn $f_assign(&mut self, rhs: Pos) {
+                *self = $op::$f(*self, rhs);
+            }
+        }
+    };
+}
+impl_set_op!(BitOr, bitor, BitOrAssign, bitor_assign, |);
+impl_set_op!(BitAnd, bitand, BitAndAssign, bitand_assign, &);
+impl_set_op!(BitXor, bitxor, BitXorAssign, bitxor_assign, ^);
+
+impl Sub for SquareSet {
+    type Output = SquareSet;
+    fn sub(self, rhs: SquareSet) -> SquareSet {
+        SquareSet(self.0 & !rhs.0)
+    }
+}
+impl Sub<Pos> for SquareSet {
+    type Output = SquareSet;
+    fn sub(self, rhs: Pos) -> SquareSet {
+        SquareSet(self.0 & !rhs.mask())
+    }
+}
+impl SubAssign for SquareSet {
+    fn sub_assign(&mut self, rhs: SquareSet) {
+        *self = *self - rhs;
+    }
+}
+impl SubAssign<Pos> for SquareSet {
+    fn sub_assign(&mut self, rhs: Pos) {
+        *self = *self - rhs;
+    }
+}
+impl Not for SquareSet {
+    type Output = SquareSet;
+    fn not(self) -> SquareSet {
+        SquareSet(!self.0)
+    }
+}
+
+impl std::fmt::Debug for SquareSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// Prints the set as a grid in the same orientation as `Board`'s `Debug`,
+/// with `x` for members and `.` for other squares.
+impl std::fmt::Display for SquareSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "  ABCDEFGH")?;
+        for row in 0..8 {
+            write!(f, "{} ", row + 1)?;
+            for col in 0..8 {
+                let pos = Pos::from_row_col(row, col).unwrap();
+                write!(f, "{}", if self.contains(pos) { 'x' } else { '.' })?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_set_ops() {
+        let a: SquareSet = [Pos::A1, Pos::D4, Pos::H8].into_iter().collect();
+        let b = SquareSet::from(Pos::D4) | Pos::E5;
+        assert_eq!(a.len(), 3);
+        assert!(a.contains(Pos::D4) && !a.contains(Pos::E5));
+        assert_eq!((a & b).iter().collect::<Vec<_>>(), vec![Pos::D4]);
+        assert_eq!(a - b, SquareSet::CORNERS & a);
+        assert_eq!((a ^ b).len(), 3);
+        assert_eq!((!a).len(), 61);
+        assert!((a & b).is_subset(a));
+        assert!(SquareSet::EMPTY.is_empty());
+        assert_eq!(a.first(), Some(Pos::H8));
+        let mut c = a;
+        c.remove(Pos::A1);
+        c.insert(Pos::B2);
+        assert_eq!(c, SquareSet::from(Pos::B2) | Pos::D4 | Pos::H8);
+    }
+    #[test]
+    fn test_shift() {
+        let d4 = SquareSet::from(Pos::D4);
+        assert_eq!(d4.shift(CompassRose::North), Pos::D3.into());
+        assert_eq!(d4.shift(CompassRose::South), Pos::D5.into());
+        assert_eq!(d4.shift(CompassRose::East), Pos::E4.into());
+        assert_eq!(d4.shift(CompassRose::West), Pos::C4.into());
+        assert_eq!(d4.shift(CompassRose::Northwest), Pos::C3.into());
+        assert_eq!(d4.shift(CompassRose::Southeast), Pos::E5.into());
+        assert_eq!(d4.shift(CompassRose::Northeast), Pos::E3.into());
+        assert_eq!(d4.shift(CompassRose::Southwest), Pos::C5.into());
+        for &d in CompassRose::iter() {
+            assert!(SquareSet::CORNERS.shift(d).len() <= 2);
+        }
+    }
+    #[test]
+    fn test_display() {
+        let s = (SquareSet::from(Pos::A1) | Pos::H8).to_string();
+        assert_eq!(s.lines().nth(1), Some("1 x......."));
+        assert_eq!(s.lines().nth(8), Some("8 .......x"));
+    }
+}
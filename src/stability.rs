@@ -0,0 +1,242 @@
+//! Stable discs: discs that can never be flipped again, whatever is played.
+//!
+//! Three sources of stability are combined:
+//! - edge stability, computed exactly for each edge by a table over all 3^8 edge patterns in
+//!   which either player may play any empty edge square in any order;
+//! - full lines, which cannot be flipped along since there is no empty square to play from;
+//! - interior propagation: a disc is stable when each of its four lines is full or has a
+//!   stable disc of the same colour next to it.
+use crate::board::{Board, CompassRose, Player, Pos, SquareSet};
+use std::sync::OnceLock;
+
+/// `edge_table()[p][o]`: discs of `p` on an 8-square line that no sequence of moves on the
+/// line can flip, with `p` and `o` the two players' discs, bit `i` being the `i`-th square.
+fn edge_table() -> &'static [[u8; 256]; 256] {
+    static TABLE: OnceLock<Box<[[u8; 256]; 256]>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = Box::new([[0u8; 256]; 256]);
+        let mut patterns: Vec<(u8, u8)> = (0..=255u8)
+            .flat_map(|p| (0..=255u8).filter(move |o| p & o == 0).map(move |o| (p, o)))
+            .collect();
+        // children have one more disc, so fill the table from the fullest patterns down
+        patterns.sort_by_key(|&(p, o)| std::cmp::Reverse((p | o).count_ones()));
+        for (p, o) in patterns {
+            let mut stable = p;
+            let mut empty = !(p | o);
+            while empty != 0 && stable != 0 {
+                let s = empty & empty.wrapping_neg();
+                empty ^= s;
+                let f = line_flips(p, o, s);
+                stable &= table[(p | s | f) as usize][(o & !f) as usize];
+                let f = line_flips(o, p, s);
+                stable &= table[(p & !f) as usize][(o | s | f) as usize];
+            }
+            table[p as usize][o as usize] = stable;
+        }
+        table
+    })
+}
+
+/// Discs of `o` flipped when `m` plays at the single bit `s` of an 8-square line.
+fn line_flips(m: u8, o: u8, s: u8) -> u8 {
+    let mut flips = 0;
+    let mut x = s << 1;
+    let mut run = 0;
+    while x & o != 0 {
+        run |= x;
+        x <<= 1;
+    }
+    if x & m != 0 {
+        flips |= run;
+    }
+    let mut x = s >> 1;
+    let mut run = 0;
+    while x & o != 0 {
+        run |= x;
+        x >>= 1;
+    }
+    if x & m != 0 {
+        flips |= run;
+    }
+    flips
+}
+
+/// The four edges, each as its squares in order along the edge.
+fn edges() -> &'static [[Pos; 8]; 4] {
+    static EDGES: OnceLock<[[Pos; 8]; 4]> = OnceLock::new();
+    EDGES.get_or_init(|| {
+        let at = |row, col| Pos::from_row_col(row, col).unwrap();
+        [
+            std::array::from_fn(|i| at(0, i as u8)),
+            std::array::from_fn(|i| at(7, i as u8)),
+            std::array::from_fn(|i| at(i as u8, 0)),
+            std::array::from_fn(|i| at(i as u8, 7)),
+        ]
+    })
+}
+
+fn gather(set: SquareSet, line: &[Pos; 8]) -> u8 {
+    line.iter()
+        .enumerate()
+        .fold(0, |acc, (i, &pos)| acc | ((set.contains(pos) as u8) << i))
+}
+
+fn scatter(bits: u8, line: &[Pos; 8]) -> SquareSet {
+    line.iter()
+        .enumerate()
+        .filter(|&(i, _)| bits & (1 << i) != 0)
+        .map(|(_, &pos)| pos)
+        .collect()
+}
+
+/// Every line of the board in one of the four axes, as masks.
+fn lines() -> &'static [Vec<SquareSet>; 4] {
+    static LINES: OnceLock<[Vec<SquareSet>; 4]> = OnceLock::new();
+    LINES.get_or_init(|| {
+        let line_of = |key: fn(Pos) -> i8| {
+            let mut lines: Vec<SquareSet> = Vec::new();
+            let mut keys: Vec<i8> = Pos::iter().map(|&pos| key(pos)).collect();
+            keys.sort_unstable();
+            keys.dedup();
+            for k in keys {
+                lines.push(Pos::iter().copied().filter(|&pos| key(pos) == k).collect());
+            }
+            lines
+        };
+        [
+            line_of(|pos| pos.row() as i8),
+            line_of(|pos| pos.col() as i8),
+            line_of(|pos| pos.row() as i8 - pos.col() as i8),
+            line_of(|pos| pos.row() as i8 + pos.col() as i8),
+        ]
+    })
+}
+
+/// Squares whose line along each axis (horizontal, vertical, both diagonals) is fully occupied.
+fn full_lines(occupied: SquareSet) -> [SquareSet; 4] {
+    let lines = lines();
+    std::array::from_fn(|axis| {
+        lines[axis]
+            .iter()
+            .filter(|&&line| line.is_subset(occupied))
+            .fold(SquareSet::EMPTY, |acc, &line| acc | line)
+    })
+}
+
+impl Board {
+    /// Discs of `p` that can never be flipped for the rest of the game.
+    ///
+    /// The result is exact on the edges and a safe under-approximation in the interior.
+    pub fn stable_discs(&self, p: Player) -> SquareSet {
+        use CompassRose::*;
+        let mine = self.get_disks(p);
+        let theirs = self.get_disks(p.opponent());
+        let table = edge_table();
+        let mut stable = SquareSet::EMPTY;
+        for line in edges() {
+            let bits = table[gather(mine, line) as usize][gather(theirs, line) as usize];
+            stable |= scatter(bits, line);
+        }
+        let full = full_lines(mine | theirs);
+        let interior = mine - edges().iter().flatten().copied().collect::<SquareSet>();
+        stable |= interior & full[0] & full[1] & full[2] & full[3];
+        loop {
+            let horizontal = stable.shift(East) | stable.shift(West) | full[0];
+            let vertical = stable.shift(North) | stable.shift(South) | full[1];
+            let diagonal = stable.shift(Northwest) | stable.shift(Southeast) | full[2];
+            let anti_diagonal = stable.shift(Northeast) | stable.shift(Southwest) | full[3];
+            let next = stable | (interior & horizontal & vertical & diagonal & anti_diagonal);
+            if next == stable {
+                return stable;
+            }
+            stable = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(positions: &[Pos]) -> SquareSet {
+        positions.iter().copied().collect()
+    }
+
+    #[test]
+    fn test_no_stable_discs_in_opening() {
+        let board = Board::initial();
+        assert_eq!(board.stable_discs(Player::Black), SquareSet::EMPTY);
+        assert_eq!(board.stable_discs(Player::White), SquareSet::EMPTY);
+    }
+
+    #[test]
+    fn test_corner_and_edge() {
+        use Pos::*;
+        let black = set(&[A1, B1, C1, A2]);
+        let white = set(&[D1, B2]);
+        let board = Board::from_disks(black, white);
+        assert_eq!(board.stable_discs(Player::Black), set(&[A1, B1, C1, A2]));
+        // black can play E1 and flip D1; B2 is inside
+        assert_eq!(board.stable_discs(Player::White), SquareSet::EMPTY);
+        // white H1 would flip B1..G1, until the edge is full
+        let black = set(&[B1, C1, D1, E1, F1, G1]);
+        let board = Board::from_disks(black, set(&[A1]));
+        assert_eq!(board.stable_discs(Player::Black), SquareSet::EMPTY);
+        assert_eq!(board.stable_discs(Player::White), set(&[A1]));
+        let board = Board::from_disks(black, set(&[A1, H1]));
+        assert_eq!(board.stable_discs(Player::Black), black);
+    }
+
+    #[test]
+    fn test_edge_with_gap() {
+        use Pos::*;
+        // A1 B1 _ D1: white can take E1 and then C1, flipping D1
+        let board = Board::from_disks(set(&[A1, B1, D1]), SquareSet::EMPTY);
+        assert_eq!(board.stable_discs(Player::Black), set(&[A1, B1]));
+    }
+
+    #[test]
+    fn test_interior_propagation() {
+        let rows_1_2: SquareSet = Pos::iter().copied().filter(|pos| pos.row() < 2).collect();
+        let board = Board::from_disks(rows_1_2, SquareSet::EMPTY);
+        assert_eq!(board.stable_discs(Player::Black), rows_1_2);
+        // a white disc on C3 is not stable, and neither is the black disc on C4 behind it
+        let board = Board::from_disks(rows_1_2 | Pos::C4, Pos::C3.into());
+        assert_eq!(board.stable_discs(Player::Black), rows_1_2);
+        assert_eq!(board.stable_discs(Player::White), SquareSet::EMPTY);
+    }
+
+    #[test]
+    fn test_full_board() {
+        let black: SquareSet = Pos::iter().copied().filter(|pos| (pos.row() + pos.col()) % 3 == 0).collect();
+        let board = Board::from_disks(black, !black);
+        assert_eq!(board.stable_discs(Player::Black), black);
+        assert_eq!(board.stable_discs(Player::White), !black);
+    }
+
+    #[test]
+    fn test_stable_discs_never_flip() {
+        use rand::seq::IteratorRandom;
+        let mut rng = rand::thread_rng();
+        for _ in 0..300 {
+            let mut board = Board::initial();
+            let mut p = Player::Black;
+            let mut stable = [SquareSet::EMPTY; 2];
+            while !board.is_final() {
+                for q in [Player::Black, Player::White] {
+                    assert!(stable[q as usize].is_subset(board.get_disks(q)), "{:?}", board);
+                    let now = board.stable_discs(q);
+                    assert!(stable[q as usize].is_subset(now), "{:?}", board);
+                    stable[q as usize] = now;
+                }
+                if let Some(action) = board.valid_moves(p).choose(&mut rng) {
+                    board = action.board;
+                }
+                p.flip();
+            }
+            for q in [Player::Black, Player::White] {
+                assert!(stable[q as usize].is_subset(board.get_disks(q)));
+            }
+        }
+    }
+}
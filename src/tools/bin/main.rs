@@ -0,0 +1,358 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use reversi::analysis::{self, AlphaBeta, Budget, Evaluator, Playouts};
+use reversi::annotate::{self, AnnotateConfig};
+use reversi::board::{Board, Player, Position};
+use reversi::enumerate::Enumerator;
+use reversi::match_runner::{self, MatchConfig};
+use reversi::perft;
+use reversi::registry::AgentSpec;
+use reversi::simulate::GameRecord;
+use reversi::sprt::{self, SprtConfig};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+#[derive(Parser, Debug)]
+#[command(about = "Reversi analysis tools")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Count leaf nodes of the game tree from the initial position
+    Perft {
+        #[arg(help = "maximum depth")]
+        depth: u32,
+        #[arg(long, default_value_t = false, help = "only run the maximum depth")]
+        single: bool,
+        #[arg(long, default_value_t = false, help = "split the count of the maximum depth by first move")]
+        divide: bool,
+    },
+    /// Count distinct positions per depth from the initial position, up to symmetry
+    Enumerate {
+        #[arg(help = "maximum depth")]
+        depth: u32,
+        #[arg(long, help = "number of worker threads [default: number of CPUs]")]
+        threads: Option<usize>,
+        #[arg(long, default_value_t = 1 << 24, help = "positions kept in memory per level before spilling")]
+        memory_limit: usize,
+        #[arg(long, help = "directory for spilled levels; without it everything stays in memory")]
+        spill_dir: Option<PathBuf>,
+    },
+    /// Play a match between two agents and report win rates and Elo difference
+    Match {
+        #[arg(help = "agent spec, e.g. random, lookahead, mcts:sims=1000 or alphabeta:depth=4")]
+        a: AgentSpec,
+        #[arg(help = "agent spec of the opponent")]
+        b: AgentSpec,
+        #[arg(short = 'n', long, default_value_t = 100, help = "number of games")]
+        games: usize,
+        #[arg(long, value_parser = match_runner::parse_opening_plies, help = "start from the distinct positions after this many plies (even)")]
+        opening_plies: Option<u32>,
+        #[arg(long, help = "file with one opening move sequence per line, e.g. F5D6C3D3")]
+        openings: Option<PathBuf>,
+        #[arg(short, long, default_value_t = false, help = "print every game")]
+        verbose: bool,
+    },
+    /// Test whether a candidate agent is stronger than a baseline with an SPRT
+    Sprt {
+        #[arg(help = "agent spec of the candidate")]
+        candidate: AgentSpec,
+        #[arg(help = "agent spec of the baseline")]
+        baseline: AgentSpec,
+        #[arg(long, default_value_t = 0.0, help = "Elo gain of the null hypothesis")]
+        elo0: f64,
+        #[arg(long, default_value_t = 10.0, help = "Elo gain of the alternative hypothesis")]
+        elo1: f64,
+        #[arg(long, default_value_t = 0.05)]
+        alpha: f64,
+        #[arg(long, default_value_t = 0.05)]
+        beta: f64,
+        #[arg(long, help = "give up after this many games")]
+        max_games: Option<usize>,
+        #[arg(long, default_value_t = 8, value_parser = match_runner::parse_opening_plies, help = "start from the distinct positions after this many plies (even)")]
+        opening_plies: u32,
+        #[arg(long, help = "number of worker threads [default: number of CPUs]")]
+        threads: Option<usize>,
+    },
+    /// Evaluate every legal move of a position and rank them
+    Analyze {
+        #[arg(
+            allow_hyphen_values = true,
+            help = "64 squares in reading order (X black, O white, - empty) and the side to move, e.g. \"---...--- X\""
+        )]
+        position: Position,
+        #[arg(long, value_enum, default_value_t = EvaluatorKind::Alphabeta)]
+        evaluator: EvaluatorKind,
+        #[arg(long, default_value_t = 1_000_000, help = "nodes to search per move")]
+        nodes: u64,
+        #[arg(long, conflicts_with = "nodes", help = "milliseconds to search per move, instead of a node budget")]
+        time_ms: Option<u64>,
+        #[arg(long, default_value_t = AlphaBeta::default().max_depth, help = "maximum depth of alphabeta")]
+        depth: usize,
+    },
+    /// Replay recorded games and mark mistakes and blunders with the better move
+    Annotate {
+        #[arg(help = "file with one game per line as written by tournament --record, e.g. F5D6C3D3... from the initial position; lines starting with # are copied")]
+        games: PathBuf,
+        #[arg(short, long, help = "write the annotated transcripts to this file instead of stdout")]
+        output: Option<PathBuf>,
+        #[arg(long, default_value_t = 200_000, help = "nodes to search per move")]
+        nodes: u64,
+        #[arg(long, conflicts_with = "nodes", help = "milliseconds to search per move, instead of a node budget")]
+        time_ms: Option<u64>,
+        #[arg(long, default_value_t = AnnotateConfig::default().exact_empties, help = "solve positions with this many empty squares or fewer exactly")]
+        exact_empties: u32,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum EvaluatorKind {
+    /// Iterative deepening alpha-beta, exact once it reaches the end of the game
+    Alphabeta,
+    /// Mean disc margin of random playouts
+    Playouts,
+}
+
+fn run_perft(depth: u32, single: bool, divide: bool) {
+    let board = Board::initial();
+    let p = Player::Black;
+    let first = if single { depth } else { 1 };
+    for d in first..=depth {
+        let start = Instant::now();
+        let nodes = perft::perft(&board, p, d);
+        let elapsed = start.elapsed().as_secs_f64();
+        let check = match perft::INITIAL_PERFT.get(d as usize) {
+            Some(&expected) if expected == nodes => "ok".to_string(),
+            Some(&expected) => format!("MISMATCH, expected {}", expected),
+            None => "unknown".to_string(),
+        };
+        println!(
+            "perft({:2}) = {:14} {:8.3}s {:12.0} nodes/s [{}]",
+            d,
+            nodes,
+            elapsed,
+            nodes as f64 / elapsed.max(1e-9),
+            check
+        );
+    }
+    if divide {
+        for (pos, nodes) in perft::divide(&board, p, depth) {
+            match pos {
+                Some(pos) => println!("{}: {}", pos, nodes),
+                None => println!("PASS: {}", nodes),
+            }
+        }
+    }
+}
+
+fn run_enumerate(
+    depth: u32,
+    threads: Option<usize>,
+    memory_limit: usize,
+    spill_dir: Option<PathBuf>,
+) -> std::io::Result<()> {
+    let mut enumerator = Enumerator::new().memory_limit(memory_limit);
+    if let Some(threads) = threads {
+        enumerator = enumerator.threads(threads);
+    }
+    if let Some(dir) = spill_dir {
+        enumerator = enumerator.spill_dir(dir);
+    }
+    let start = Instant::now();
+    println!("depth       unique     terminal  runs  elapsed");
+    enumerator.run(&Board::initial(), Player::Black, depth, |s| {
+        println!(
+            "{:5} {:12} {:12} {:5} {:7.3}s",
+            s.depth,
+            s.unique,
+            s.terminal,
+            s.spilled_runs,
+            start.elapsed().as_secs_f64()
+        );
+    })?;
+    Ok(())
+}
+
+fn run_match(
+    a: AgentSpec,
+    b: AgentSpec,
+    games: usize,
+    opening_plies: Option<u32>,
+    openings: Option<PathBuf>,
+    verbose: bool,
+) -> std::io::Result<()> {
+    let mut config = MatchConfig::new(games);
+    if let Some(plies) = opening_plies {
+        config.openings = match_runner::openings_after(plies);
+    }
+    if let Some(path) = openings {
+        for line in std::fs::read_to_string(&path)?.lines().filter(|l| !l.trim().is_empty()) {
+            match match_runner::opening_from_moves(line) {
+                Some(board) => config.openings.push(board),
+                None => eprintln!("skipping opening {:?}: illegal or White to move", line),
+            }
+        }
+    }
+    let stats = match_runner::play_match(&mut a.build(), &mut b.build(), &config, |game, stats| {
+        if verbose {
+            println!(
+                "game {:4}: {} {} as {}, margin {:+3}, {} moves",
+                game.index + 1,
+                a,
+                if game.margin > 0 { "wins" } else if game.margin < 0 { "loses" } else { "draws" },
+                if game.a_is_black { "Black" } else { "White" },
+                game.margin,
+                game.length
+            );
+        } else {
+            eprint!("\r{}", stats);
+        }
+    });
+    if !verbose {
+        eprintln!();
+    }
+    println!("{} vs {}: {}", a, b, stats);
+    Ok(())
+}
+
+fn run_sprt(
+    candidate: AgentSpec,
+    baseline: AgentSpec,
+    test: SprtConfig,
+    opening_plies: u32,
+    threads: Option<usize>,
+) {
+    let config = MatchConfig::new(0).with_openings(match_runner::openings_after(opening_plies));
+    let threads = threads.unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+    let (lower, upper) = test.bounds();
+    let outcome = sprt::run(
+        &test,
+        &config,
+        || candidate.build(),
+        || baseline.build(),
+        threads,
+        |_, stats, llr| eprint!("\rllr {:+.3} ({:+.3}, {:+.3}), {}", llr, lower, upper, stats),
+    );
+    eprintln!();
+    println!("{:?} after {} games, llr {:+.3}", outcome.result, outcome.stats.games(), outcome.llr);
+    println!("{}", outcome.stats);
+}
+
+fn run_analyze(position: Position, evaluator: EvaluatorKind, budget: Budget, depth: usize) {
+    let mut evaluator: Box<dyn Evaluator> = match evaluator {
+        EvaluatorKind::Alphabeta => Box::new(AlphaBeta { max_depth: depth }),
+        EvaluatorKind::Playouts => Box::new(Playouts),
+    };
+    println!("{}\n", reversi::render::View::new(position.board).hints(position.to_move));
+    println!("rank move  score        depth      nodes  pv");
+    for (i, analysis) in analysis::analyze(&position.board, position.to_move, &mut *evaluator, budget).iter().enumerate() {
+        let evaluation = &analysis.evaluation;
+        let pv: Vec<_> = evaluation.pv.iter().map(|&at| analysis::move_name(at)).collect();
+        println!(
+            "{:4} {:5} {:12} {:5} {:10}  {}",
+            i + 1,
+            analysis::move_name(analysis.at),
+            evaluation.score.to_string(),
+            evaluation.depth,
+            evaluation.nodes,
+            pv.join(" ")
+        );
+    }
+}
+
+fn run_annotate(games: PathBuf, output: Option<PathBuf>, config: AnnotateConfig) -> std::io::Result<()> {
+    let mut out: Box<dyn Write> = match output {
+        Some(path) => Box::new(std::io::BufWriter::new(std::fs::File::create(path)?)),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    let text = std::fs::read_to_string(&games)?;
+    let lines: Vec<_> = text.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+    let n_games = lines.iter().filter(|l| !l.starts_with('#')).count();
+    let mut i = 0;
+    for line in lines {
+        if line.starts_with('#') {
+            writeln!(out, "{}", line)?;
+            continue;
+        }
+        i += 1;
+        writeln!(out, "Game {}: {}", i, line)?;
+        match line.parse::<GameRecord>() {
+            Ok(record) => {
+                let game = annotate::annotate(&record, &config, |turn| eprint!("\rgame {}/{}, move {}", i, n_games, turn.ply));
+                eprintln!();
+                writeln!(out, "{}\n", game)?;
+            }
+            Err(err) => {
+                eprintln!("skipping game {}: {}", i, err);
+                writeln!(out, "skipped: {}\n", err)?;
+            }
+        }
+    }
+    out.flush()
+}
+
+fn main() -> std::io::Result<()> {
+    let cli = Cli::parse();
+    match cli.command {
+        Command::Perft { depth, single, divide } => run_perft(depth, single, divide),
+        Command::Enumerate {
+            depth,
+            threads,
+            memory_limit,
+            spill_dir,
+        } => run_enumerate(depth, threads, memory_limit, spill_dir)?,
+        Command::Match {
+            a,
+            b,
+            games,
+            opening_plies,
+            openings,
+            verbose,
+        } => run_match(a, b, games, opening_plies, openings, verbose)?,
+        Command::Sprt {
+            candidate,
+            baseline,
+            elo0,
+            elo1,
+            alpha,
+            beta,
+            max_games,
+            opening_plies,
+            threads,
+        } => {
+            let mut test = SprtConfig::new(elo0, elo1).alpha(alpha).beta(beta);
+            if let Some(max_games) = max_games {
+                test = test.max_games(max_games);
+            }
+            run_sprt(candidate, baseline, test, opening_plies, threads)
+        }
+        Command::Analyze {
+            position,
+            evaluator,
+            nodes,
+            time_ms,
+            depth,
+        } => {
+            let budget = time_ms.map_or(Budget::Nodes(nodes), |ms| Budget::Time(Duration::from_millis(ms)));
+            run_analyze(position, evaluator, budget, depth)
+        }
+        Command::Annotate {
+            games,
+            output,
+            nodes,
+            time_ms,
+            exact_empties,
+        } => {
+            let config = AnnotateConfig {
+                budget: time_ms.map_or(Budget::Nodes(nodes), |ms| Budget::Time(Duration::from_millis(ms))),
+                exact_empties,
+                ..Default::default()
+            };
+            run_annotate(games, output, config)?
+        }
+    }
+    Ok(())
+}
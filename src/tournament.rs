@@ -0,0 +1,302 @@
+//! Round-robin and gauntlet tournaments between several agents, with a crosstable and
+//! maximum-likelihood Elo ratings.
+use crate::agent::Agent;
+use crate::match_runner::{self, GameSummary, MatchConfig, MatchStats};
+use crate::simulate::GameRecord;
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    /// Every player meets every other player.
+    RoundRobin,
+    /// The first player meets every other player; the others do not meet each other.
+    Gauntlet,
+}
+
+impl Schedule {
+    /// Pairs of players meeting each other, each pair once.
+    pub fn pairings(self, n_players: usize) -> Vec<(usize, usize)> {
+        match self {
+            Schedule::RoundRobin => (0..n_players)
+                .flat_map(|i| (i + 1..n_players).map(move |j| (i, j)))
+                .collect(),
+            Schedule::Gauntlet => (1..n_players).map(|j| (0, j)).collect(),
+        }
+    }
+}
+
+/// Results of every pair of players, `get(i, j)` being from `i`'s point of view.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Crosstable {
+    pub names: Vec<String>,
+    results: Vec<Vec<MatchStats>>,
+}
+
+impl Crosstable {
+    pub fn new(names: Vec<String>) -> Self {
+        let n = names.len();
+        Self {
+            names,
+            results: vec![vec![MatchStats::default(); n]; n],
+        }
+    }
+    /// Records `game`, which is from `a`'s point of view, for both `a` and `b`.
+    pub fn add(&mut self, a: usize, b: usize, game: &GameSummary) {
+        self.results[a][b].add(game);
+        let reversed = GameSummary {
+            a_is_black: !game.a_is_black,
+            margin: -game.margin,
+            ..*game
+        };
+        self.results[b][a].add(&reversed);
+    }
+    pub fn get(&self, a: usize, b: usize) -> &MatchStats {
+        &self.results[a][b]
+    }
+    /// Totals of player `i` against everyone.
+    pub fn total(&self, i: usize) -> MatchStats {
+        self.results[i].iter().fold(MatchStats::default(), |mut acc, s| {
+            acc.wins += s.wins;
+            acc.losses += s.losses;
+            acc.draws += s.draws;
+            acc.total_margin += s.total_margin;
+            acc.total_length += s.total_length;
+            acc
+        })
+    }
+
+    /// Elo ratings maximising the Bradley-Terry likelihood of the results, a draw counting as
+    /// half a win and half a loss, with their mean at 0.
+    ///
+    /// As in BayesElo, every pair that has played gets one virtual draw, which keeps the
+    /// ratings finite when a player has won or lost all its games.
+    pub fn ratings(&self) -> Vec<f64> {
+        let n = self.names.len();
+        let mut points = vec![vec![0.0; n]; n];
+        let mut games = vec![vec![0.0; n]; n];
+        for i in 0..n {
+            for j in 0..n {
+                let s = &self.results[i][j];
+                if s.games() > 0 {
+                    points[i][j] = s.wins as f64 + 0.5 * s.draws as f64 + 0.5;
+                    games[i][j] = s.games() as f64 + 1.0;
+                }
+            }
+        }
+        // minorization-maximization (Hunter, 2004) on the strengths gamma = 10^(elo / 400)
+        let mut gamma = vec![1.0f64; n];
+        for _ in 0..10000 {
+            let mut max_change = 0.0f64;
+            for i in 0..n {
+                let won: f64 = points[i].iter().sum();
+                let denominator: f64 = (0..n).map(|j| games[i][j] / (gamma[i] + gamma[j])).sum();
+                if denominator == 0.0 {
+                    continue;
+                }
+                let next = won / denominator;
+                max_change = max_change.max((next / gamma[i]).ln().abs());
+                gamma[i] = next;
+            }
+            let mean_log = gamma.iter().map(|g| g.ln()).sum::<f64>() / n as f64;
+            gamma.iter_mut().for_each(|g| *g /= mean_log.exp());
+            if max_change < 1e-10 {
+                break;
+            }
+        }
+        gamma.iter().map(|g| 400.0 * g.log10()).collect()
+    }
+
+    /// Writes one line per player with its rating, totals and score against each opponent.
+    pub fn write_csv(&self, w: &mut impl Write) -> std::io::Result<()> {
+        let ratings = self.ratings();
+        write!(w, "name,rating,games,wins,losses,draws,score")?;
+        for name in &self.names {
+            write!(w, ",{}", csv_field(name))?;
+        }
+        writeln!(w)?;
+        for (i, name) in self.names.iter().enumerate() {
+            let total = self.total(i);
+            write!(
+                w,
+                "{},{:.1},{},{},{},{},{:.4}",
+                csv_field(name),
+                ratings[i],
+                total.games(),
+                total.wins,
+                total.losses,
+                total.draws,
+                total.score()
+            )?;
+            for j in 0..self.names.len() {
+                match self.results[i][j].games() {
+                    0 => write!(w, ",")?,
+                    _ => write!(w, ",{:.4}", self.results[i][j].score())?,
+                }
+            }
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+}
+
+/// Quotes `s` if it contains a comma, a quote or a line break, as agent specs may.
+fn csv_field(s: &str) -> std::borrow::Cow<'_, str> {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\"")).into()
+    } else {
+        s.into()
+    }
+}
+
+/// Players sorted by rating, each row giving the points scored against every opponent.
+impl std::fmt::Display for Crosstable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ratings = self.ratings();
+        let mut order: Vec<_> = (0..self.names.len()).collect();
+        order.sort_by(|&i, &j| ratings[j].total_cmp(&ratings[i]));
+        let width = self.names.iter().map(|n| n.len()).max().unwrap_or(0).max(4);
+        write!(f, "{:>3} {:width$} {:>7} {:>6} {:>6}", "#", "name", "elo", "games", "score")?;
+        for k in 1..=order.len() {
+            write!(f, " {:>7}", k)?;
+        }
+        writeln!(f)?;
+        for (rank, &i) in order.iter().enumerate() {
+            let total = self.total(i);
+            write!(
+                f,
+                "{:>3} {:width$} {:>+7.1} {:>6} {:>5.1}%",
+                rank + 1,
+                self.names[i],
+                ratings[i],
+                total.games(),
+                100.0 * total.score()
+            )?;
+            for &j in &order {
+                let s = &self.results[i][j];
+                if i == j || s.games() == 0 {
+                    write!(f, " {:>7}", "-")?;
+                } else {
+                    let points = s.wins as f64 + 0.5 * s.draws as f64;
+                    write!(f, " {:>7}", format!("{}/{}", points, s.games()))?;
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Plays `config.n_games` games for every pairing of `schedule` on `threads` threads, building
+/// fresh agents for every game with `make_agent(player)`.
+///
+/// `on_game(a, b, game, record, crosstable)` is called on the calling thread after each game,
+/// `game` being from `a`'s point of view.
+pub fn run<F>(
+    names: Vec<String>,
+    make_agent: F,
+    schedule: Schedule,
+    config: &MatchConfig,
+    threads: usize,
+    mut on_game: impl FnMut(usize, usize, &GameSummary, &GameRecord, &Crosstable),
+) -> Crosstable
+where
+    F: Fn(usize) -> Box<dyn Agent> + Sync,
+{
+    let jobs: Vec<_> = schedule
+        .pairings(names.len())
+        .into_iter()
+        .flat_map(|(a, b)| (0..config.n_games).map(move |i| (a, b, i)))
+        .collect();
+    let mut crosstable = Crosstable::new(names);
+    let next_job = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    std::thread::scope(|s| {
+        for _ in 0..threads.max(1) {
+            let sender = sender.clone();
+            let (jobs, next_job, make_agent) = (&jobs, &next_job, &make_agent);
+            s.spawn(move || {
+                while let Some(&(a, b, i)) = jobs.get(next_job.fetch_add(1, Ordering::Relaxed)) {
+                    let (board, a_is_black) = config.game(i);
+                    let (mut agent_a, mut agent_b) = (make_agent(a), make_agent(b));
+                    let (game, record) = match_runner::play_recorded_game(&mut agent_a, &mut agent_b, board, a_is_black, i);
+                    if sender.send((a, b, game, record)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+        for (a, b, game, record) in receiver {
+            crosstable.add(a, b, &game);
+            on_game(a, b, &game, &record, &crosstable);
+        }
+    });
+    crosstable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{OneStepLookaheadAgent, RandomAgent};
+
+    fn game(margin: i32) -> GameSummary {
+        GameSummary {
+            index: 0,
+            a_is_black: true,
+            margin,
+            length: 60,
+        }
+    }
+
+    #[test]
+    fn test_pairings() {
+        assert_eq!(Schedule::RoundRobin.pairings(3), vec![(0, 1), (0, 2), (1, 2)]);
+        assert_eq!(Schedule::Gauntlet.pairings(3), vec![(0, 1), (0, 2)]);
+        assert!(Schedule::RoundRobin.pairings(1).is_empty());
+    }
+
+    #[test]
+    fn test_ratings() {
+        let mut table = Crosstable::new(vec!["a".into(), "b".into(), "c,d".into()]);
+        for _ in 0..30 {
+            table.add(0, 1, &game(10));
+            table.add(1, 2, &game(10));
+        }
+        for _ in 0..10 {
+            table.add(0, 1, &game(-10));
+            table.add(1, 2, &game(-10));
+        }
+        let ratings = table.ratings();
+        assert!(ratings.iter().sum::<f64>().abs() < 1e-6);
+        // 75% against each other: about 190 Elo apart, shrunk a little by the prior
+        let d = ratings[0] - ratings[1];
+        assert!(170.0 < d && d < 195.0, "{:?}", ratings);
+        assert!((ratings[1] - ratings[2] - d).abs() < 1e-6, "{:?}", ratings);
+        assert_eq!(table.get(1, 0).wins, 10);
+        assert_eq!(table.total(1).games(), 80);
+        let mut csv = Vec::new();
+        table.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().count(), 4);
+        assert!(csv.lines().next().unwrap().ends_with(",a,b,\"c,d\""));
+        assert!(csv.lines().nth(2).unwrap().starts_with("b,0.0,80,40,40,0,0.5000,0.2500,,0.7500"));
+    }
+
+    #[test]
+    fn test_run() {
+        let names = vec!["random".to_string(), "lookahead".to_string(), "random2".to_string()];
+        let make_agent = |i: usize| -> Box<dyn Agent> {
+            match i {
+                1 => Box::new(OneStepLookaheadAgent),
+                _ => Box::new(RandomAgent),
+            }
+        };
+        let mut n = 0;
+        let table = run(names, make_agent, Schedule::RoundRobin, &MatchConfig::new(4), 3, |_, _, _, _, _| n += 1);
+        assert_eq!(n, 12);
+        assert!((0..3).all(|i| table.total(i).games() == 8));
+        assert!(table.to_string().lines().count() == 4);
+    }
+}
@@ -0,0 +1,85 @@
+use clap::{CommandFactory, Parser};
+use reversi::match_runner::{self, MatchConfig};
+use reversi::registry::{self, AgentSpec};
+use reversi::tournament::{self, Schedule};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(about = "Play a tournament between agents and rate them")]
+struct Cli {
+    #[arg(help = "agents, e.g. random, lookahead, mcts:sims=20000,c=1.2 or alphabeta:depth=6")]
+    agents: Vec<AgentSpec>,
+    #[arg(long, help = "file with further agent specs, one per line")]
+    agents_file: Option<PathBuf>,
+    #[arg(long, default_value_t = false, help = "only play the first agent against the others")]
+    gauntlet: bool,
+    #[arg(short = 'n', long, default_value_t = 20, help = "number of games per pairing")]
+    games: usize,
+    #[arg(long, value_parser = match_runner::parse_opening_plies, help = "start from the distinct positions after this many plies (even)")]
+    opening_plies: Option<u32>,
+    #[arg(long, help = "number of worker threads [default: number of CPUs]")]
+    threads: Option<usize>,
+    #[arg(long, help = "also write the crosstable and ratings to this CSV file")]
+    csv: Option<PathBuf>,
+    #[arg(long, help = "also write every game to this file, for tools annotate")]
+    record: Option<PathBuf>,
+}
+
+fn main() -> std::io::Result<()> {
+    let mut cli = Cli::parse();
+    if let Some(path) = &cli.agents_file {
+        let specs = registry::parse_specs(&std::fs::read_to_string(path)?)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))?;
+        cli.agents.extend(specs);
+    }
+    if cli.agents.len() < 2 {
+        Cli::command()
+            .error(clap::error::ErrorKind::TooFewValues, "at least two agents are needed")
+            .exit();
+    }
+    let mut config = MatchConfig::new(cli.games);
+    if let Some(plies) = cli.opening_plies {
+        config.openings = match_runner::openings_after(plies);
+    }
+    let schedule = if cli.gauntlet { Schedule::Gauntlet } else { Schedule::RoundRobin };
+    let threads = cli
+        .threads
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+    let names: Vec<String> = cli.agents.iter().map(|s| s.to_string()).collect();
+    let total = schedule.pairings(names.len()).len() * cli.games;
+    let mut played = 0;
+    let mut records = cli.record.as_ref().map(|path| File::create(path).map(BufWriter::new)).transpose()?;
+    let mut record_error = None;
+    let table = tournament::run(
+        names.clone(),
+        |i| cli.agents[i].build(),
+        schedule,
+        &config,
+        threads,
+        |a, b, game, record, _| {
+            if let Some(out) = &mut records {
+                let (black, white) = if game.a_is_black { (a, b) } else { (b, a) };
+                let header = format!("# {} vs {}, game {}", names[black], names[white], game.index + 1);
+                if let Err(e) = writeln!(out, "{}\n{}", header, record) {
+                    record_error.get_or_insert(e);
+                }
+            }
+            played += 1;
+            eprint!("\r{}/{} games", played, total);
+        },
+    );
+    eprintln!();
+    if let Some(e) = record_error {
+        return Err(e);
+    }
+    if let Some(mut out) = records {
+        out.flush()?;
+    }
+    print!("{}", table);
+    if let Some(path) = cli.csv {
+        table.write_csv(&mut BufWriter::new(File::create(path)?))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,116 @@
+//! Full server conversations scripted in advance and run over in-memory transports.
+use reversi::agent::{Agent, GameEnd};
+use reversi::board::{Action, Board, Player, Pos};
+use reversi::command::{Cmd, Milliseconds, Recovery, Session, SessionError, SessionHandler, Stat};
+
+/// Plays the first legal move, so that whole games can be scripted.
+struct FirstMove;
+impl Agent for FirstMove {
+    fn select_move(&mut self, board: &Board, p: Player) -> Option<Action> {
+        board.valid_moves(p).next()
+    }
+}
+
+/// What the server sends to a client playing `bw` and what it expects back, in a game where
+/// both sides play [`FirstMove`].
+fn game(bw: Player) -> (Vec<Cmd>, Vec<Cmd>) {
+    let mut server = vec![Cmd::start(bw, "bob", Milliseconds(60000))];
+    let mut client = Vec::new();
+    let mut board = Board::initial();
+    let mut p = Player::Black;
+    loop {
+        let action = FirstMove.select_move(&board, p);
+        if let Some(action) = action {
+            board = action.board;
+        }
+        let pos = action.map(|a| a.at);
+        if p == bw {
+            client.push(Cmd::Move(pos));
+            server.push(Cmd::ack(Milliseconds(59000)));
+        }
+        if board.is_final() {
+            break;
+        }
+        if p != bw {
+            server.push(Cmd::Move(pos));
+        }
+        p.flip();
+    }
+    let end = GameEnd::from_board(&board, bw, "DOUBLE_PASS");
+    server.push(Cmd::end(end.result, end.my_score, end.opponent_score, end.reason));
+    (server, client)
+}
+
+fn lines(cmds: &[Cmd]) -> String {
+    cmds.iter().map(|cmd| format!("{}\n", cmd)).collect()
+}
+
+#[derive(Default)]
+struct Counter {
+    moves: usize,
+    ends: Vec<GameEnd>,
+    stat: Vec<Stat>,
+}
+impl SessionHandler for Counter {
+    fn on_move(&mut self, _p: Player, _pos: Option<Pos>, _board: &Board) {
+        self.moves += 1;
+    }
+    fn on_end(&mut self, end: &GameEnd) {
+        self.ends.push(end.clone());
+    }
+    fn on_bye(&mut self, stat: &[Stat]) {
+        self.stat = stat.to_vec();
+    }
+}
+
+#[test]
+fn test_full_match() {
+    let (mut server, mut client) = (Vec::new(), vec![Cmd::Open { name: "me".into() }]);
+    for bw in [Player::Black, Player::White] {
+        let (s, c) = game(bw);
+        server.extend(s);
+        client.extend(c);
+    }
+    server.push(Cmd::Bye { stat: Vec::new() });
+    let input = lines(&server);
+    let mut output = Vec::new();
+    let mut counter = Counter::default();
+    Session::new("me")
+        .recovery(Recovery::Abort)
+        .run_with(&mut FirstMove, input.as_bytes(), &mut output, &mut counter)
+        .unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), lines(&client));
+    assert_eq!(counter.ends.len(), 2);
+    assert!(counter.ends.iter().all(|end| end.my_score + end.opponent_score > 4));
+    let n_moves = server.iter().chain(&client).filter(|cmd| matches!(cmd, Cmd::Move(_))).count();
+    assert_eq!(counter.moves, n_moves);
+}
+
+#[test]
+fn test_recorded_transcript() {
+    let transcript = "START WHITE bob 1000\nNONSENSE\nMOVE F5\nACK 900\nEND LOSE 3 61 TIMEOUT\nBYE bob 58 1 0 me -58 0 1\n";
+    let mut output = Vec::new();
+    let mut counter = Counter::default();
+    Session::new("me")
+        .run_with(&mut FirstMove, transcript.as_bytes(), &mut output, &mut counter)
+        .unwrap();
+    let after_f5 = Board::initial().valid_moves(Player::Black).find(|a| a.at == Pos::F5).unwrap().board;
+    let reply = after_f5.valid_moves(Player::White).next().unwrap().at;
+    assert_eq!(String::from_utf8(output).unwrap(), format!("OPEN me\nMOVE {}\n", reply));
+    assert_eq!(counter.moves, 2);
+    assert_eq!(counter.ends[0].reason, "TIMEOUT");
+    assert_eq!(counter.stat.len(), 2);
+}
+
+#[test]
+fn test_errors() {
+    let run = |recovery, transcript: &str| {
+        Session::new("me").recovery(recovery).run(&mut FirstMove, transcript.as_bytes(), std::io::sink())
+    };
+    let result = run(Recovery::Abort, "START BLACK bob 1000\nACK 900\n");
+    assert!(matches!(result, Err(SessionError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof));
+    let result = run(Recovery::Abort, "START WHITE bob 1000\nMOVE A1\n");
+    assert!(matches!(result, Err(SessionError::IllegalOpponentMove { pos: Pos::A1, .. })));
+    let result = run(Recovery::AbandonGame, "START WHITE bob 1000\nMOVE A1\nMOVE B1\nEND LOSE 1 4 ILLEGAL_MOVE\nBYE\n");
+    assert!(result.is_ok());
+}